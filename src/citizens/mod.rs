@@ -3,17 +3,43 @@
 //! This module defines the 9 core citizens that form the minimal complete set
 //! for any living world in the CLD system.
 
+use crate::span::{Span, Spanned};
+use serde::{Deserialize, Serialize};
+
 /// Value enum to represent different types of values in CLD files
-#[derive(Debug, Clone)]
+///
+/// Serialized as `{"type": "<variant>", "value": <payload>}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
 pub enum Value {
     String(String),
     Number(f64),
     Boolean(bool),
-    List(Vec<Value>),
+    List(Vec<Spanned<Value>>),
     Identifier(String),
+    /// Nested `{ key: value, ... }` entries, in declaration order
+    Map(Fields),
 }
 
 impl Value {
+    /// Name of the value's type, as used in diagnostics
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Number(_) => "number",
+            Value::Boolean(_) => "boolean",
+            Value::List(_) => "list",
+            Value::Identifier(_) => "identifier",
+            Value::Map(_) => "map",
+        }
+    }
+
+    /// The value's type with its article, e.g. "an identifier"
+    pub fn described(&self) -> String {
+        let article = if matches!(self, Value::Identifier(_)) { "an" } else { "a" };
+        format!("{} {}", article, self.type_name())
+    }
+
     /// Get the string representation of the value
     pub fn as_string(&self) -> Option<&String> {
         match self {
@@ -39,7 +65,7 @@ impl Value {
     }
 
     /// Get the list representation of the value
-    pub fn as_list(&self) -> Option<&Vec<Value>> {
+    pub fn as_list(&self) -> Option<&Vec<Spanned<Value>>> {
         match self {
             Value::List(l) => Some(l),
             _ => None,
@@ -53,6 +79,14 @@ impl Value {
             _ => None,
         }
     }
+
+    /// Get the map representation of the value
+    pub fn as_map(&self) -> Option<&Fields> {
+        match self {
+            Value::Map(m) => Some(m),
+            _ => None,
+        }
+    }
 }
 
 impl std::fmt::Display for Value {
@@ -72,69 +106,361 @@ impl std::fmt::Display for Value {
                 write!(f, "]")
             }
             Value::Identifier(i) => write!(f, "{}", i),
+            Value::Map(m) => {
+                write!(f, "{{")?;
+                for (i, field) in m.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", field.key, field.value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// A single `key: value` entry inside a citizen body
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Field {
+    /// Canonical key (see [`crate::schema::WELL_KNOWN_FIELDS`])
+    pub key: String,
+    /// The key as written, when it was an alias of the canonical key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    #[serde(default)]
+    pub key_span: Span,
+    pub value: Spanned<Value>,
+}
+
+impl Field {
+    /// The key as it was written in the source
+    pub fn written_key(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.key)
+    }
+
+    /// Span covering the whole entry, from the key to the end of the value
+    pub fn span(&self) -> Span {
+        Span {
+            end: self.value.span.end,
+            ..self.key_span
+        }
+    }
+}
+
+/// The fields of a citizen, kept in declaration order
+///
+/// Repeated keys are preserved so that validation can report them; lookups
+/// by key return the last declaration, matching how later entries override
+/// earlier ones.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Fields(Vec<Field>);
+
+impl Fields {
+    /// Create an empty field list
+    pub fn new() -> Self {
+        Fields(Vec::new())
+    }
+
+    /// Append a field
+    pub fn push(&mut self, field: Field) {
+        self.0.push(field);
+    }
+
+    /// Get the value of the last field named `key`
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.field(key).map(|field| &field.value.node)
+    }
+
+    /// Get the last field named `key`, including its spans
+    pub fn field(&self, key: &str) -> Option<&Field> {
+        self.0.iter().rev().find(|field| field.key == key)
+    }
+
+    /// Check whether a field named `key` is present
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.field(key).is_some()
+    }
+
+    /// Iterate over the fields in declaration order
+    pub fn iter(&self) -> std::slice::Iter<'_, Field> {
+        self.0.iter()
+    }
+
+    /// Number of declared fields, counting repeated keys
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Check whether no fields were declared
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Point every span of the fields, including nested values, into file
+    /// `file` of a source map
+    pub fn set_file(&mut self, file: usize) {
+        for field in &mut self.0 {
+            field.key_span.file = file;
+            set_value_file(&mut field.value, file);
+        }
+    }
+
+    /// Layer `patch` on top of these fields
+    ///
+    /// A patch field replaces every earlier field with the same key, in the
+    /// position of the first one; new keys are appended.
+    pub fn merge(&mut self, patch: Fields) {
+        for field in patch.0 {
+            match self.0.iter().position(|existing| existing.key == field.key) {
+                Some(index) => {
+                    self.0.retain(|existing| existing.key != field.key);
+                    self.0.insert(index, field);
+                }
+                None => self.0.push(field),
+            }
+        }
+    }
+}
+
+fn set_value_file(value: &mut Spanned<Value>, file: usize) {
+    value.span.file = file;
+    match &mut value.node {
+        Value::List(items) => {
+            for item in items {
+                set_value_file(item, file);
+            }
+        }
+        Value::Map(fields) => fields.set_file(file),
+        _ => {}
+    }
+}
+
+impl<'a> IntoIterator for &'a Fields {
+    type Item = &'a Field;
+    type IntoIter = std::slice::Iter<'a, Field>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+/// Separator between the parts of a qualified name, e.g. `north::war`
+pub const NAMESPACE_SEPARATOR: &str = "::";
+
+/// The name `name` has when declared inside `namespace`
+///
+/// The root namespace is the empty string.
+pub fn qualify(namespace: &str, name: &str) -> String {
+    if namespace.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}{}{}", namespace, NAMESPACE_SEPARATOR, name)
+    }
+}
+
+/// The namespace part of a qualified name; empty for the root namespace
+pub fn namespace_of(name: &str) -> &str {
+    name.rsplit_once(NAMESPACE_SEPARATOR)
+        .map(|(namespace, _)| namespace)
+        .unwrap_or("")
+}
+
+/// How a citizen declaration relates to earlier ones with the same name
+///
+/// Written between the name and the body: `@Event[war] merge { ... }`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeclMode {
+    /// A new citizen; declaring the same name twice is an error
+    #[default]
+    Declare,
+    /// Add to or replace individual fields of an earlier declaration
+    Merge,
+    /// Replace an earlier declaration entirely
+    Override,
+}
+
+impl DeclMode {
+    /// Whether this is a plain declaration
+    pub fn is_declare(&self) -> bool {
+        *self == DeclMode::Declare
+    }
+
+    /// The keyword as written in the source, if any
+    pub fn keyword(&self) -> Option<&'static str> {
+        match self {
+            DeclMode::Declare => None,
+            DeclMode::Merge => Some("merge"),
+            DeclMode::Override => Some("override"),
         }
     }
 }
 
 /// The world's initial singularity (time origin, entropy, core contradiction)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Origin {
     pub name: String,
-    pub fields: std::collections::HashMap<String, Value>,
+    #[serde(default)]
+    pub name_span: Span,
+    #[serde(default, skip_serializing_if = "DeclMode::is_declare")]
+    pub mode: DeclMode,
+    pub fields: Fields,
+    #[serde(default)]
+    pub span: Span,
 }
 
 /// The time manifold (directed causal graph, supports main axis/branch axes)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Timeline {
     pub name: String,
-    pub fields: std::collections::HashMap<String, Value>,
+    #[serde(default)]
+    pub name_span: Span,
+    #[serde(default, skip_serializing_if = "DeclMode::is_declare")]
+    pub mode: DeclMode,
+    pub fields: Fields,
+    #[serde(default)]
+    pub span: Span,
 }
 
 /// The minimal causal change unit (includes emotional peaks, world entropy change)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Event {
     pub name: String,
-    pub fields: std::collections::HashMap<String, Value>,
+    #[serde(default)]
+    pub name_span: Span,
+    #[serde(default, skip_serializing_if = "DeclMode::is_declare")]
+    pub mode: DeclMode,
+    pub fields: Fields,
+    #[serde(default)]
+    pub span: Span,
 }
 
 /// Immutable historical cornerstone
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CoreEvent {
     pub name: String,
-    pub fields: std::collections::HashMap<String, Value>,
+    #[serde(default)]
+    pub name_span: Span,
+    #[serde(default, skip_serializing_if = "DeclMode::is_declare")]
+    pub mode: DeclMode,
+    pub fields: Fields,
+    #[serde(default)]
+    pub span: Span,
 }
 
 /// Behavioral niche (strategy, stress response)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Niche {
     pub name: String,
-    pub fields: std::collections::HashMap<String, Value>,
+    #[serde(default)]
+    pub name_span: Span,
+    #[serde(default, skip_serializing_if = "DeclMode::is_declare")]
+    pub mode: DeclMode,
+    pub fields: Fields,
+    #[serde(default)]
+    pub span: Span,
 }
 
 /// Historical semantic layer (rules and capabilities of time periods)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Era {
     pub name: String,
-    pub fields: std::collections::HashMap<String, Value>,
+    #[serde(default)]
+    pub name_span: Span,
+    #[serde(default, skip_serializing_if = "DeclMode::is_declare")]
+    pub mode: DeclMode,
+    pub fields: Fields,
+    #[serde(default)]
+    pub span: Span,
 }
 
 /// Dynamic content generator (pure function)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Generator {
     pub name: String,
-    pub fields: std::collections::HashMap<String, Value>,
+    #[serde(default)]
+    pub name_span: Span,
+    #[serde(default, skip_serializing_if = "DeclMode::is_declare")]
+    pub mode: DeclMode,
+    pub fields: Fields,
+    #[serde(default)]
+    pub span: Span,
 }
 
 /// Collective memory bank (layered storage, compression)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Memory {
     pub name: String,
-    pub fields: std::collections::HashMap<String, Value>,
+    #[serde(default)]
+    pub name_span: Span,
+    #[serde(default, skip_serializing_if = "DeclMode::is_declare")]
+    pub mode: DeclMode,
+    pub fields: Fields,
+    #[serde(default)]
+    pub span: Span,
 }
 
 /// World immune system (monitoring, prediction, repair)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Immune {
     pub name: String,
-    pub fields: std::collections::HashMap<String, Value>,
-}
\ No newline at end of file
+    #[serde(default)]
+    pub name_span: Span,
+    #[serde(default, skip_serializing_if = "DeclMode::is_declare")]
+    pub mode: DeclMode,
+    pub fields: Fields,
+    #[serde(default)]
+    pub span: Span,
+}
+
+/// Common accessors shared by all citizen kinds
+pub trait CitizenData {
+    /// The citizen kind as written after `@`, e.g. `"CoreEvent"`
+    fn kind(&self) -> &'static str;
+    fn name(&self) -> &str;
+    fn name_span(&self) -> Span;
+    fn mode(&self) -> DeclMode;
+    fn fields(&self) -> &Fields;
+    fn fields_mut(&mut self) -> &mut Fields;
+    fn span(&self) -> Span;
+}
+
+macro_rules! impl_citizen_data {
+    ($($citizen:ident),*) => {
+        $(
+            impl CitizenData for $citizen {
+                fn kind(&self) -> &'static str {
+                    stringify!($citizen)
+                }
+
+                fn name(&self) -> &str {
+                    &self.name
+                }
+
+                fn name_span(&self) -> Span {
+                    self.name_span
+                }
+
+                fn mode(&self) -> DeclMode {
+                    self.mode
+                }
+
+                fn fields(&self) -> &Fields {
+                    &self.fields
+                }
+
+                fn fields_mut(&mut self) -> &mut Fields {
+                    &mut self.fields
+                }
+
+                fn span(&self) -> Span {
+                    self.span
+                }
+            }
+        )*
+    };
+}
+
+impl_citizen_data!(Origin, Timeline, Event, CoreEvent, Niche, Era, Generator, Memory, Immune);
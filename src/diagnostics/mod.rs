@@ -0,0 +1,346 @@
+//! Diagnostics for CLD files
+//!
+//! A [`Diagnostic`] carries a message plus labelled spans, and can be rendered
+//! against the original source as a rustc-style snippet:
+//!
+//! ```text
+//! error: @CoreEvent 'big_bang' referenced in Origin.core_anchors is not defined
+//!  --> examples/hello_cosmos.cld:4:20
+//!   |
+//! 4 |     core_anchors: [big_bang]
+//!   |                    ^^^^^^^^ no @CoreEvent with this name
+//! ```
+
+use crate::span::{SourceMap, Span};
+use serde::{Deserialize, Serialize};
+
+/// How serious a diagnostic is
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    #[default]
+    Error,
+    Warning,
+    Note,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+/// A span annotated with a short message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    /// Primary labels are underlined with `^`, secondary ones with `-`
+    pub primary: bool,
+}
+
+/// A message about a CLD source, optionally pointing at source locations
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<String>,
+    /// The CLD v6 rule broken, such as `!Era`, for rule violations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rule: Option<String>,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    /// Create a diagnostic with the given severity and message
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity,
+            code: None,
+            rule: None,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    /// Create an error diagnostic
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message)
+    }
+
+    /// Create a warning diagnostic
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, message)
+    }
+
+    /// Attach an error code such as `E0001`
+    pub fn with_code(mut self, code: impl Into<String>) -> Self {
+        self.code = Some(code.into());
+        self
+    }
+
+    /// Attach the name of the rule broken, such as `!Era`
+    pub fn with_rule(mut self, rule: impl Into<String>) -> Self {
+        self.rule = Some(rule.into());
+        self
+    }
+
+    /// Whether `--allow`-style `filter` names this diagnostic's code or rule
+    ///
+    /// Rules match with or without their leading `!`, so `!Era` and `Era`
+    /// both select the era rules.
+    pub fn matches(&self, filter: &str) -> bool {
+        self.code.as_deref() == Some(filter)
+            || self
+                .rule
+                .as_deref()
+                .is_some_and(|rule| rule == filter || rule.trim_start_matches('!') == filter)
+    }
+
+    /// Point at the main offending span
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: true,
+        });
+        self
+    }
+
+    /// Point at a related span, such as a previous declaration
+    pub fn with_secondary_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: false,
+        });
+        self
+    }
+
+    /// Add a trailing `= note:` line
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// The first primary label's span, if any
+    pub fn primary_span(&self) -> Option<Span> {
+        self.labels.iter().find(|label| label.primary).map(|label| label.span)
+    }
+
+    /// Render the diagnostic as a rustc-style snippet of `source`
+    ///
+    /// `file_name` is only used for the `-->` location line.
+    pub fn render(&self, file_name: &str, source: &str) -> String {
+        self.render_with(|_| Some((file_name, source)))
+    }
+
+    /// Render the diagnostic against the files of a multi-file world
+    ///
+    /// Labels are grouped by file; the group holding the primary label comes
+    /// first and the others are introduced with `:::`.
+    pub fn render_in(&self, sources: &SourceMap) -> String {
+        self.render_with(|file| sources.get(file).map(|f| (f.name.as_str(), f.source.as_str())))
+    }
+
+    fn render_with<'a>(&self, file_of: impl Fn(usize) -> Option<(&'a str, &'a str)>) -> String {
+        let mut out = String::new();
+        match &self.code {
+            Some(code) => out.push_str(&format!("{}[{}]: {}\n", self.severity, code, self.message)),
+            None => out.push_str(&format!("{}: {}\n", self.severity, self.message)),
+        }
+
+        let mut labels: Vec<&Label> = self.labels.iter().collect();
+        let primary_file = self
+            .primary_span()
+            .or_else(|| labels.first().map(|label| label.span))
+            .map(|span| span.file);
+        labels.sort_by_key(|label| {
+            (
+                Some(label.span.file) != primary_file,
+                label.span.file,
+                label.span.line,
+                label.span.column,
+                !label.primary,
+            )
+        });
+
+        let gutter = labels
+            .iter()
+            .map(|label| label.span.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let pad = " ".repeat(gutter);
+
+        let mut previous: Option<(usize, u32)> = None;
+        for label in &labels {
+            let (file_name, source) = file_of(label.span.file).unwrap_or(("<unknown>", ""));
+            let line = label.span.line;
+            if previous.map(|(file, _)| file) != Some(label.span.file) {
+                // Introduce the snippet of a new file with its location
+                let span = match previous {
+                    None => self.primary_span().unwrap_or(label.span),
+                    Some(_) => label.span,
+                };
+                let arrow = if previous.is_none() { "-->" } else { ":::" };
+                out.push_str(&format!("{}{} {}:{}:{}\n", pad, arrow, file_name, span.line, span.column));
+                out.push_str(&format!("{} |\n", pad));
+                previous = None;
+            }
+            let text = source_line(source, line);
+            if previous.map(|(_, line)| line) != Some(line) {
+                if let Some((_, previous_line)) = previous {
+                    if line > previous_line + 1 {
+                        out.push_str("...\n");
+                    }
+                }
+                out.push_str(&format!("{:>width$} | {}\n", line, text.replace('\t', "    "), width = gutter));
+            }
+            out.push_str(&format!("{} | {}\n", pad, underline(text, label)));
+            previous = Some((label.span.file, line));
+        }
+
+        if !labels.is_empty() && !self.notes.is_empty() {
+            out.push_str(&format!("{} |\n", pad));
+        }
+        for note in &self.notes {
+            out.push_str(&format!("{} = note: {}\n", pad, note));
+        }
+        out
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.code {
+            Some(code) => write!(f, "{}[{}]: {}", self.severity, code, self.message),
+            None => write!(f, "{}: {}", self.severity, self.message),
+        }
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+/// Get the text of a 1-based line, without its line terminator
+fn source_line(source: &str, line: u32) -> &str {
+    source
+        .lines()
+        .nth(line.saturating_sub(1) as usize)
+        .unwrap_or("")
+        .trim_end_matches('\r')
+}
+
+/// Build the marker line placed under `text` for `label`
+///
+/// Spans that continue past the end of the line are underlined up to the
+/// end of the line.
+fn underline(text: &str, label: &Label) -> String {
+    let start_column = label.span.column.max(1) as usize;
+    let offset = text
+        .char_indices()
+        .nth(start_column - 1)
+        .map(|(index, _)| index)
+        .unwrap_or(text.len());
+    let length = (label.span.end - label.span.start).min(text.len() - offset);
+
+    let lead: usize = text[..offset].chars().map(display_width).sum();
+    let marked: usize = text[offset..offset + length]
+        .chars()
+        .map(display_width)
+        .sum::<usize>()
+        .max(1);
+    let marker = if label.primary { "^" } else { "-" };
+
+    let mut out = " ".repeat(lead);
+    out.push_str(&marker.repeat(marked));
+    if !label.message.is_empty() {
+        out.push(' ');
+        out.push_str(&label.message);
+    }
+    out
+}
+
+/// Terminal column width of a character
+///
+/// East Asian wide characters (CJK ideographs, Hangul, full-width forms)
+/// take two columns, so carets stay aligned under Chinese identifiers. Tabs
+/// are expanded to four spaces when the source line is printed.
+fn display_width(c: char) -> usize {
+    match c as u32 {
+        0x09 => 4,
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+/// Machine-readable summary of the diagnostics produced for one source
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    pub source: String,
+    /// Every file of a multi-file world, indexed by `span.file`; only
+    /// present when imports pulled in other files
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<String>,
+    pub errors: usize,
+    pub warnings: usize,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Report {
+    /// Collect diagnostics for `source`, counting them by severity
+    pub fn new(source: &str, diagnostics: Vec<Diagnostic>) -> Self {
+        let count = |severity| {
+            diagnostics
+                .iter()
+                .filter(|diagnostic| diagnostic.severity == severity)
+                .count()
+        };
+        Report {
+            source: source.to_string(),
+            files: Vec::new(),
+            errors: count(Severity::Error),
+            warnings: count(Severity::Warning),
+            diagnostics,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_code_or_rule() {
+        let diagnostic = Diagnostic::error("era overlap").with_code("E0401").with_rule("!Era");
+        assert!(diagnostic.matches("E0401"));
+        assert!(diagnostic.matches("!Era"));
+        assert!(diagnostic.matches("Era"));
+        assert!(!diagnostic.matches("!Emotion"));
+        assert!(!diagnostic.matches("W0401"));
+    }
+
+    #[test]
+    fn rule_is_serialized_only_when_set() {
+        let plain = serde_json::to_value(Diagnostic::error("x").with_code("E0001")).unwrap();
+        assert!(plain.get("rule").is_none());
+        let rule = serde_json::to_value(Diagnostic::error("x").with_rule("!Era")).unwrap();
+        assert_eq!(rule["rule"], "!Era");
+    }
+}
@@ -0,0 +1,337 @@
+//! Emotional arcs
+//!
+//! A timeline's arc is the sequence of beats its fired events strike: each
+//! event with an `emotion_peak` contributes that emotion's valence and
+//! intensity, and each event without one a neutral beat of intensity 0.
+//! Arcs are traced by running the timeline (see
+//! [`crate::runtime::Simulation`]), so events that wait on their `requires`
+//! or are refused by their era are left out, as they would be in a run.
+//!
+//! Analysis looks for two pacing problems: flat stretches, where several
+//! beats in a row stay within a narrow band of intensity below the peaks,
+//! and runs of more consecutive peaks than a reader can take.
+
+use super::{Tone, Vocabulary};
+use crate::runtime::{Outcome, Simulation};
+use crate::timeline::Branches;
+use crate::world::World;
+
+/// The emotional content of one fired event
+#[derive(Debug, Clone, PartialEq)]
+pub struct Beat {
+    pub tick: u64,
+    pub event: String,
+    /// The event's `emotion_peak` label, if it has one
+    pub emotion: Option<String>,
+    /// From -1 (dark) to 1 (bright)
+    pub valence: f64,
+    /// From 0 to 100
+    pub intensity: f64,
+}
+
+/// The beats of one timeline, in the order they happened
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmotionalArc {
+    /// The timeline traced, or `None` for a world run in causal order
+    pub timeline: Option<String>,
+    pub beats: Vec<Beat>,
+}
+
+/// Thresholds for [`EmotionalArc::analyze`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ArcSettings {
+    /// Intensity at which a beat counts as a peak
+    pub peak: f64,
+    /// Most peaks allowed in a row
+    pub max_peaks: usize,
+    /// Widest band of intensity a flat stretch stays within
+    pub flat_tolerance: f64,
+    /// Fewest beats that make a flat stretch
+    pub min_flat: usize,
+}
+
+impl Default for ArcSettings {
+    fn default() -> Self {
+        ArcSettings {
+            peak: 70.0,
+            max_peaks: 3,
+            flat_tolerance: 10.0,
+            min_flat: 4,
+        }
+    }
+}
+
+/// A pacing problem found in an arc
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArcFinding {
+    /// Beats from `from` to `to` (ticks, inclusive) stay within
+    /// `low..=high` intensity
+    Flat { from: u64, to: u64, beats: usize, low: f64, high: f64 },
+    /// Beats from `from` to `to` (ticks, inclusive) are all peaks
+    Peaks { from: u64, to: u64, beats: usize, max: usize },
+}
+
+impl std::fmt::Display for ArcFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ArcFinding::Flat { from, to, beats, low, high } => write!(
+                f,
+                "flat from tick {} to tick {}: {} beats between intensity {} and {}",
+                from, to, beats, low, high
+            ),
+            ArcFinding::Peaks { from, to, beats, max } => write!(
+                f,
+                "{} peaks in a row from tick {} to tick {} (at most {})",
+                beats, from, to, max
+            ),
+        }
+    }
+}
+
+impl EmotionalArc {
+    /// The arc of the events `simulation` has fired so far
+    pub fn trace(simulation: &Simulation, vocabulary: &Vocabulary) -> Self {
+        let beats = simulation
+            .history()
+            .iter()
+            .filter(|record| record.outcome == Outcome::Fired)
+            .filter_map(|record| {
+                let event = simulation.typed().event(record.event.as_ref()?)?;
+                let (emotion, tone) = match &event.emotion_peak {
+                    Some(emotion) => (Some(emotion.label.clone()), vocabulary.feel(emotion)),
+                    None => (None, Tone { valence: 0.0, intensity: 0.0 }),
+                };
+                Some(Beat {
+                    tick: record.tick,
+                    event: event.name.clone(),
+                    emotion,
+                    valence: tone.valence,
+                    intensity: tone.intensity,
+                })
+            })
+            .collect();
+        EmotionalArc {
+            timeline: simulation.timeline().map(|timeline| timeline.name.clone()),
+            beats,
+        }
+    }
+
+    /// The highest intensity reached, or 0 for an empty arc
+    pub fn climax(&self) -> f64 {
+        self.beats.iter().map(|beat| beat.intensity).fold(0.0, f64::max)
+    }
+
+    /// Flat stretches and runs of too many peaks, in order of their start
+    pub fn analyze(&self, settings: &ArcSettings) -> Vec<ArcFinding> {
+        let mut findings = Vec::new();
+        let beats = &self.beats;
+
+        // Grow each stretch as far as it stays within the band; a reported
+        // stretch is not reported again in part. Peaks are left to the
+        // check below.
+        let is_peak = |beat: &Beat| beat.intensity >= settings.peak;
+        let mut start = 0;
+        while start < beats.len() {
+            if is_peak(&beats[start]) {
+                start += 1;
+                continue;
+            }
+            let (mut low, mut high) = (beats[start].intensity, beats[start].intensity);
+            let mut end = start + 1;
+            while end < beats.len() && !is_peak(&beats[end]) {
+                let intensity = beats[end].intensity;
+                if intensity.max(high) - intensity.min(low) > settings.flat_tolerance {
+                    break;
+                }
+                low = low.min(intensity);
+                high = high.max(intensity);
+                end += 1;
+            }
+            if end - start >= settings.min_flat.max(2) {
+                findings.push(ArcFinding::Flat {
+                    from: beats[start].tick,
+                    to: beats[end - 1].tick,
+                    beats: end - start,
+                    low,
+                    high,
+                });
+                start = end;
+            } else {
+                start += 1;
+            }
+        }
+
+        let mut start = 0;
+        while start < beats.len() {
+            let run = beats[start..].iter().take_while(|beat| is_peak(beat)).count();
+            if run > settings.max_peaks {
+                findings.push(ArcFinding::Peaks {
+                    from: beats[start].tick,
+                    to: beats[start + run - 1].tick,
+                    beats: run,
+                    max: settings.max_peaks,
+                });
+            }
+            start += run.max(1);
+        }
+
+        findings.sort_by_key(|finding| match finding {
+            ArcFinding::Flat { from, .. } | ArcFinding::Peaks { from, .. } => *from,
+        });
+        findings
+    }
+}
+
+/// The arc of every timeline with a history
+///
+/// Each timeline is run until its history is exhausted or `ticks` ticks
+/// have passed, whichever comes first. A world without such a timeline
+/// gets a single arc over its events in causal order.
+pub fn arcs(world: &World, vocabulary: &Vocabulary, ticks: u64) -> Vec<EmotionalArc> {
+    let references = world.reference_graph();
+    let branches = Branches::build(world, &references, &mut Vec::new());
+    let mut simulations: Vec<Simulation> = branches
+        .iter()
+        .filter(|branch| !branch.sequence.is_empty())
+        .filter_map(|branch| Simulation::on_timeline(world, &branch.timeline.name))
+        .collect();
+    if simulations.is_empty() {
+        simulations.push(Simulation::new(world));
+    }
+    simulations
+        .into_iter()
+        .map(|mut simulation| {
+            while !simulation.is_finished() && simulation.state().tick < ticks {
+                simulation.step();
+            }
+            EmotionalArc::trace(&simulation, vocabulary)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{tone_of, validate_emotions};
+    use super::*;
+    use crate::citizens::Value;
+    use crate::diagnostics::Severity;
+    use crate::error::CldError;
+    use crate::parser::CLDParser;
+
+    /// An arc with one beat per intensity, at ticks 1, 2, ...
+    fn arc(intensities: &[f64]) -> EmotionalArc {
+        let beats = intensities
+            .iter()
+            .enumerate()
+            .map(|(i, &intensity)| Beat {
+                tick: i as u64 + 1,
+                event: format!("e{}", i + 1),
+                emotion: None,
+                valence: 0.0,
+                intensity,
+            })
+            .collect();
+        EmotionalArc { timeline: None, beats }
+    }
+
+    fn world(source: &str) -> World {
+        let document = CLDParser::parse_document(source).expect("test source parses");
+        let (world, errors) = World::build_document(document);
+        assert!(errors.is_empty(), "{:?}", errors);
+        world
+    }
+
+    #[test]
+    fn finds_flat_stretches_within_the_tolerance() {
+        let settings = ArcSettings::default();
+        // 10..20 spans exactly the tolerance; 21 would widen it past
+        let findings = arc(&[10.0, 20.0, 15.0, 12.0, 21.0, 60.0]).analyze(&settings);
+        assert_eq!(
+            findings,
+            [ArcFinding::Flat { from: 1, to: 4, beats: 4, low: 10.0, high: 20.0 }]
+        );
+
+        // One beat short of `min_flat`
+        assert!(arc(&[10.0, 12.0, 14.0, 60.0]).analyze(&settings).is_empty());
+
+        // A stretch needs at least two beats, whatever `min_flat` says
+        let settings = ArcSettings { min_flat: 0, ..settings };
+        assert_eq!(
+            arc(&[10.0, 40.0, 41.0]).analyze(&settings),
+            [ArcFinding::Flat { from: 2, to: 3, beats: 2, low: 40.0, high: 41.0 }]
+        );
+    }
+
+    #[test]
+    fn peaks_break_flat_stretches() {
+        let findings = arc(&[10.0, 10.0, 90.0, 10.0, 10.0]).analyze(&ArcSettings::default());
+        assert!(findings.is_empty(), "{:?}", findings);
+    }
+
+    #[test]
+    fn finds_runs_of_too_many_peaks() {
+        let settings = ArcSettings::default();
+        // Three peaks in a row is the most allowed; the peak threshold counts
+        assert!(arc(&[70.0, 80.0, 90.0, 30.0]).analyze(&settings).is_empty());
+        assert_eq!(
+            arc(&[30.0, 70.0, 80.0, 90.0, 75.0, 30.0, 95.0]).analyze(&settings),
+            [ArcFinding::Peaks { from: 2, to: 5, beats: 4, max: 3 }]
+        );
+        // A run at the very end of the arc
+        assert_eq!(
+            arc(&[30.0, 70.0, 80.0, 90.0, 75.0]).analyze(&settings),
+            [ArcFinding::Peaks { from: 2, to: 5, beats: 4, max: 3 }]
+        );
+    }
+
+    #[test]
+    fn an_empty_arc_has_no_findings() {
+        let empty = arc(&[]);
+        assert!(empty.analyze(&ArcSettings::default()).is_empty());
+        assert_eq!(empty.climax(), 0.0);
+    }
+
+    #[test]
+    fn tones_need_a_valence_within_bounds() {
+        let world = world(
+            r#"
+            @Origin[o] {
+                emotions: {
+                    bright: { valence: 1, intensity: 20 },
+                    blinding: { valence: 1.5 },
+                    silent: { intensity: 10 }
+                }
+            }
+            "#,
+        );
+        let emotions = world
+            .origin
+            .as_ref()
+            .and_then(|origin| origin.fields.get("emotions"))
+            .and_then(Value::as_map)
+            .expect("emotions are a map");
+        let tones: Vec<Result<Tone, String>> = emotions.iter().map(tone_of).collect();
+        assert_eq!(tones[0], Ok(Tone { valence: 1.0, intensity: 20.0 }));
+        assert_eq!(tones[1], Err("has valence 1.5, outside -1..1".to_string()));
+        assert_eq!(tones[2], Err("has no `valence`".to_string()));
+    }
+
+    #[test]
+    fn unknown_emotions_are_warnings() {
+        let world = world(
+            r#"
+            @Origin[o] { emotions: { longing: { valence: -0.2 } } }
+            @Event[a] { emotion_peak: longing }
+            @Event[b] { emotion_peak: { label: wonder, intensity: 80 } }
+            @Event[c] { emotion_peak: wonderr }
+            "#,
+        );
+        let errors = validate_emotions(&world);
+        let [CldError::RuleViolation { rule, message, severity, .. }] = &errors[..] else {
+            panic!("expected one warning, got {:?}", errors);
+        };
+        assert_eq!(rule, "!Emotion");
+        assert_eq!(*severity, Severity::Warning);
+        assert!(message.contains("unknown emotion 'wonderr'"), "{}", message);
+    }
+}
@@ -0,0 +1,218 @@
+//! Emotion vocabulary
+//!
+//! Every emotion an event's `emotion_peak` names has a tone: a valence
+//! from -1 (dark) to 1 (bright) and a default intensity from 0 to 100. The
+//! built-in vocabulary covers common labels; the Origin may add its own or
+//! retune the built-in ones:
+//!
+//! ```text
+//! @Origin[cosmos] {
+//!     emotions: {
+//!         wonder: { valence: 0.9, intensity: 75 },
+//!         longing: { valence: -0.2 }
+//!     }
+//! }
+//! ```
+//!
+//! An event's own `intensity` takes precedence over its emotion's default.
+//! See [`arc`] for the emotional curves of timelines.
+
+use crate::citizens::{Field, Value};
+use crate::error::CldError;
+use crate::typed::{Emotion, TypedOrigin};
+use crate::world::World;
+use std::collections::BTreeMap;
+
+pub mod arc;
+
+pub use arc::{arcs, ArcFinding, ArcSettings, Beat, EmotionalArc};
+
+/// Intensity of an emotion that does not set one
+pub const DEFAULT_INTENSITY: f64 = 50.0;
+
+/// The built-in emotions, as (label, valence, intensity)
+const BUILTIN: &[(&str, f64, f64)] = &[
+    ("wonder", 0.8, 70.0),
+    ("awe", 0.6, 80.0),
+    ("creation", 0.7, 60.0),
+    ("joy", 0.9, 60.0),
+    ("triumph", 0.9, 85.0),
+    ("hope", 0.6, 40.0),
+    ("curiosity", 0.5, 30.0),
+    ("calm", 0.3, 10.0),
+    ("melancholy", -0.4, 30.0),
+    ("tension", -0.3, 50.0),
+    ("loss", -0.6, 60.0),
+    ("fear", -0.7, 70.0),
+    ("grief", -0.8, 75.0),
+    ("dread", -0.8, 80.0),
+    ("despair", -1.0, 85.0),
+    ("rage", -0.9, 90.0),
+];
+
+/// How an emotion feels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tone {
+    /// From -1 (dark) to 1 (bright)
+    pub valence: f64,
+    /// Default intensity, from 0 to 100
+    pub intensity: f64,
+}
+
+/// The emotions a world knows, by label
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vocabulary {
+    tones: BTreeMap<String, Tone>,
+}
+
+impl Default for Vocabulary {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl Vocabulary {
+    /// The built-in emotions
+    pub fn builtin() -> Self {
+        let tones = BUILTIN
+            .iter()
+            .map(|&(label, valence, intensity)| (label.to_string(), Tone { valence, intensity }))
+            .collect();
+        Vocabulary { tones }
+    }
+
+    /// The built-in emotions, extended by `Origin.emotions`
+    ///
+    /// Entries that are not valid tones are skipped; validation reports
+    /// them.
+    pub fn from_origin(origin: Option<&TypedOrigin>) -> Self {
+        let mut vocabulary = Self::builtin();
+        for field in origin.iter().flat_map(|origin| origin.emotions.iter()) {
+            if let Ok(tone) = tone_of(field) {
+                vocabulary.define(&field.key, tone);
+            }
+        }
+        vocabulary
+    }
+
+    /// Add the emotion `label`, replacing any previous tone
+    pub fn define(&mut self, label: &str, tone: Tone) {
+        self.tones.insert(label.to_string(), tone);
+    }
+
+    /// The tone of `label`, if the vocabulary knows it
+    pub fn tone(&self, label: &str) -> Option<Tone> {
+        self.tones.get(label).copied()
+    }
+
+    /// Every emotion, in label order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Tone)> {
+        self.tones.iter().map(|(label, tone)| (label.as_str(), *tone))
+    }
+
+    /// The valence and intensity of an event's `emotion_peak`
+    ///
+    /// Unknown labels are neutral, with the default intensity unless the
+    /// event sets one.
+    pub fn feel(&self, emotion: &Emotion) -> Tone {
+        let tone = self.tone(&emotion.label).unwrap_or(Tone {
+            valence: 0.0,
+            intensity: DEFAULT_INTENSITY,
+        });
+        Tone {
+            valence: tone.valence,
+            intensity: emotion.intensity.unwrap_or(tone.intensity),
+        }
+    }
+}
+
+/// The tone declared by one entry of `Origin.emotions`, or why it is not one
+fn tone_of(field: &Field) -> Result<Tone, String> {
+    let Some(entry) = field.value.as_map() else {
+        return Err("must be a map with a `valence` and optional `intensity`".to_string());
+    };
+    let valence = match entry.get("valence") {
+        Some(Value::Number(valence)) if (-1.0..=1.0).contains(valence) => *valence,
+        Some(Value::Number(valence)) => return Err(format!("has valence {}, outside -1..1", valence)),
+        Some(_) => return Err("has a valence that is not a number".to_string()),
+        None => return Err("has no `valence`".to_string()),
+    };
+    let intensity = match entry.get("intensity") {
+        None => DEFAULT_INTENSITY,
+        Some(Value::Number(intensity)) if (0.0..=100.0).contains(intensity) => *intensity,
+        Some(Value::Number(intensity)) => return Err(format!("has intensity {}, outside 0..100", intensity)),
+        Some(_) => return Err("has an intensity that is not a number".to_string()),
+    };
+    Ok(Tone { valence, intensity })
+}
+
+/// Check `Origin.emotions` and every event's `emotion_peak`
+///
+/// Malformed tones and intensities outside 0..100 are errors; labels the
+/// vocabulary does not know are warnings, since they are usually typos.
+pub fn validate_emotions(world: &World) -> Vec<CldError> {
+    let mut errors = Vec::new();
+    let mut vocabulary = Vocabulary::builtin();
+
+    if let Some(emotions) = world.origin.as_ref().and_then(|origin| origin.fields.get("emotions")).and_then(Value::as_map) {
+        for field in emotions.iter() {
+            match tone_of(field) {
+                Ok(tone) => vocabulary.define(&field.key, tone),
+                Err(problem) => errors.push(CldError::rule(
+                    "!Emotion",
+                    format!("Emotion '{}' {}", field.key, problem),
+                    Some(field.value.span),
+                )),
+            }
+        }
+    }
+
+    let events = world
+        .declaration_order()
+        .iter()
+        .filter(|citizen| citizen.kind == "Event" || citizen.kind == "CoreEvent")
+        .filter_map(|citizen| world.citizen(&citizen.kind, &citizen.name));
+    for event in events {
+        let Some(field) = event.fields().field("emotion_peak") else {
+            continue;
+        };
+        // Values of the wrong shape are reported by the schema check
+        let (label, span) = match &field.value.node {
+            Value::String(label) | Value::Identifier(label) => (label, field.value.span),
+            Value::Map(map) => {
+                let Some(label) = map.field("label") else { continue };
+                let (Value::String(text) | Value::Identifier(text)) = &label.value.node else { continue };
+                if let Some(intensity) = map.field("intensity") {
+                    match intensity.value.as_number() {
+                        Some(number) if !(0.0..=100.0).contains(&number) => errors.push(CldError::rule(
+                            "!Emotion",
+                            format!(
+                                "{} '{}' has emotion intensity {}, outside 0..100",
+                                event.kind(),
+                                event.name(),
+                                number
+                            ),
+                            Some(intensity.value.span),
+                        )),
+                        _ => {}
+                    }
+                }
+                (text, label.value.span)
+            }
+            _ => continue,
+        };
+        if vocabulary.tone(label).is_none() {
+            errors.push(CldError::rule_warning(
+                "!Emotion",
+                format!(
+                    "{} '{}' has unknown emotion '{}'; declare it in Origin.emotions",
+                    event.kind(),
+                    event.name(),
+                    label
+                ),
+                Some(span),
+            ));
+        }
+    }
+    errors
+}
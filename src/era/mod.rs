@@ -0,0 +1,295 @@
+//! Era intervals
+//!
+//! An `@Era` runs from its `start` event up to, but not including, its `end`
+//! event on one timeline's history, or to the end of that history if it has
+//! no `end`. The timeline is the one named by the era's `timeline` field,
+//! or else the first timeline whose history contains the start event. Eras
+//! that cannot be placed on any history are reported and left out.
+//!
+//! Because intervals are half-open, an era may end at the very event where
+//! the next one starts without the two overlapping.
+
+use crate::diagnostics::{Label, Severity};
+use crate::error::CldError;
+use crate::resolve::{CitizenRef, ReferenceGraph};
+use crate::timeline::Branches;
+use crate::world::World;
+use std::collections::HashMap;
+
+mod rules;
+
+pub use rules::{validate_era_rules, Breach, EraRules};
+
+/// An era placed on a timeline
+#[derive(Debug, Clone, PartialEq)]
+pub struct EraInterval {
+    pub era: CitizenRef,
+    pub timeline: CitizenRef,
+    /// Position of the start event in the timeline's history
+    pub start: usize,
+    /// Position one past the era's last event
+    pub end: usize,
+}
+
+impl EraInterval {
+    /// Whether the event at `position` of the timeline's history falls in
+    /// the era
+    pub fn contains(&self, position: usize) -> bool {
+        self.start <= position && position < self.end
+    }
+
+    /// Whether the two eras share at least one event
+    pub fn overlaps(&self, other: &EraInterval) -> bool {
+        self.timeline == other.timeline && self.start < other.end && other.start < self.end
+    }
+}
+
+/// Every placed era of a world, with the histories they are placed on
+#[derive(Debug, Clone, Default)]
+pub struct Eras {
+    intervals: Vec<EraInterval>,
+    histories: HashMap<CitizenRef, Vec<CitizenRef>>,
+}
+
+impl Eras {
+    /// Place the eras of `world` on the timeline histories in `branches`
+    ///
+    /// Eras that fit on no timeline's history, whose start or end is not
+    /// part of their timeline's history, or that end before they start, are
+    /// reported in `errors` and left out.
+    pub fn build(
+        world: &World,
+        references: &ReferenceGraph,
+        branches: &Branches,
+        errors: &mut Vec<CldError>,
+    ) -> Self {
+        let mut eras = Eras::default();
+
+        for era in world.eras.values() {
+            let era_ref = CitizenRef::of(era);
+            let target = |field| references.targets(&era_ref, field).next();
+            // A missing or unresolved start is reported by the schema check
+            // and reference resolution
+            let Some(start) = target("start") else {
+                continue;
+            };
+            let branch = match target("timeline") {
+                Some(timeline) => branches.get(timeline),
+                None => branches.iter().find(|branch| branch.sequence.contains(start)),
+            };
+            let Some(branch) = branch else {
+                // An unresolved `timeline` is reported by reference resolution
+                if !era.fields.contains_key("timeline") {
+                    errors.push(CldError::rule(
+                        "!Era",
+                        format!(
+                            "Era '{}' cannot be placed on any timeline: no timeline's history contains its start '{}'",
+                            era.name, start.name
+                        ),
+                        Some(era.name_span),
+                    ));
+                }
+                continue;
+            };
+            let history = &branch.sequence;
+            let not_in_history = |event: &CitizenRef, field: &str| {
+                CldError::rule(
+                    "!Era",
+                    format!(
+                        "Era '{}' {} at '{}', which is not part of the history of '{}'",
+                        era.name, field, event.name, branch.timeline.name
+                    ),
+                    Some(era.name_span),
+                )
+            };
+
+            let Some(start_position) = history.iter().position(|event| event == start) else {
+                errors.push(not_in_history(start, "starts"));
+                continue;
+            };
+            let end_position = match target("end") {
+                Some(end) => match history.iter().position(|event| event == end) {
+                    Some(position) if position <= start_position => {
+                        errors.push(CldError::rule(
+                            "!Era",
+                            format!(
+                                "Era '{}' ends at '{}', which does not come after its start '{}'",
+                                era.name, end.name, start.name
+                            ),
+                            Some(era.name_span),
+                        ));
+                        continue;
+                    }
+                    Some(position) => position,
+                    None => {
+                        errors.push(not_in_history(end, "ends"));
+                        continue;
+                    }
+                },
+                None => history.len(),
+            };
+
+            eras.histories
+                .entry(branch.timeline.clone())
+                .or_insert_with(|| history.clone());
+            eras.intervals.push(EraInterval {
+                era: era_ref,
+                timeline: branch.timeline.clone(),
+                start: start_position,
+                end: end_position,
+            });
+        }
+        eras
+    }
+
+    /// All placed eras, in declaration order
+    pub fn iter(&self) -> impl Iterator<Item = &EraInterval> {
+        self.intervals.iter()
+    }
+
+    /// The placed eras of `timeline`, in declaration order
+    pub fn on_timeline<'a>(&'a self, timeline: &'a CitizenRef) -> impl Iterator<Item = &'a EraInterval> + 'a {
+        self.intervals
+            .iter()
+            .filter(move |interval| &interval.timeline == timeline)
+    }
+
+    /// The history an era of `timeline` is placed on
+    pub fn history(&self, timeline: &CitizenRef) -> &[CitizenRef] {
+        self.histories.get(timeline).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// The events of `interval`, in order
+    pub fn events(&self, interval: &EraInterval) -> &[CitizenRef] {
+        &self.history(&interval.timeline)[interval.start..interval.end]
+    }
+
+    /// Every era active at `event` on `timeline`
+    ///
+    /// A valid world has at most one; overlapping eras are all returned.
+    pub fn active_at(&self, timeline: &CitizenRef, event: &CitizenRef) -> Vec<&EraInterval> {
+        let Some(position) = self.history(timeline).iter().position(|e| e == event) else {
+            return Vec::new();
+        };
+        self.intervals
+            .iter()
+            .filter(|interval| &interval.timeline == timeline && interval.contains(position))
+            .collect()
+    }
+
+    /// The era active at `event` on `timeline`, if exactly one is
+    pub fn era_at(&self, timeline: &CitizenRef, event: &CitizenRef) -> Option<&CitizenRef> {
+        match self.active_at(timeline, event).as_slice() {
+            [only] => Some(&only.era),
+            _ => None,
+        }
+    }
+}
+
+/// Report overlapping eras, and gaps between eras where no era is active
+///
+/// Gaps are warnings: events before the first era or after the last one
+/// are not considered gaps.
+pub fn validate_eras(world: &World, eras: &Eras) -> Vec<CldError> {
+    let mut errors = Vec::new();
+    let mut timelines: Vec<&CitizenRef> = Vec::new();
+    for interval in eras.iter() {
+        if !timelines.contains(&&interval.timeline) {
+            timelines.push(&interval.timeline);
+        }
+    }
+
+    for timeline in timelines {
+        let history = eras.history(timeline);
+        let intervals: Vec<&EraInterval> = eras.on_timeline(timeline).collect();
+
+        for (i, later) in intervals.iter().enumerate() {
+            for earlier in intervals[..i].iter().filter(|earlier| earlier.overlaps(later)) {
+                let from = &history[earlier.start.max(later.start)];
+                errors.push(CldError::RuleViolation {
+                    rule: "!Era".to_string(),
+                    message: format!(
+                        "Era '{}' overlaps era '{}' on timeline '{}' from '{}'",
+                        later.era.name, earlier.era.name, timeline.name, from.name
+                    ),
+                    span: world.name_span(&later.era),
+                    severity: Severity::Error,
+                    related: world.name_span(&earlier.era)
+                        .map(|span| Label {
+                            span,
+                            message: "overlapping era declared here".to_string(),
+                            primary: false,
+                        })
+                        .into_iter()
+                        .collect(),
+                });
+            }
+        }
+
+        let mut sorted = intervals.clone();
+        sorted.sort_by_key(|interval| (interval.start, interval.end));
+        let mut covered_to = sorted.first().map(|interval| interval.end).unwrap_or(0);
+        for interval in sorted.iter().skip(1) {
+            if interval.start > covered_to {
+                errors.push(CldError::rule_warning(
+                    "!Era",
+                    format!(
+                        "No era is active on timeline '{}' from '{}' until era '{}' starts",
+                        timeline.name, history[covered_to].name, interval.era.name
+                    ),
+                    world.name_span(&interval.era),
+                ));
+            }
+            covered_to = covered_to.max(interval.end);
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::CLDParser;
+
+    /// Eras of `source` and the problems found placing them
+    fn place(source: &str) -> (Eras, Vec<CldError>) {
+        let document = CLDParser::parse_document(source).expect("test source parses");
+        let (world, errors) = World::build_document(document);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let references = world.reference_graph();
+        let mut errors = Vec::new();
+        let branches = Branches::build(&world, &references, &mut errors);
+        let eras = Eras::build(&world, &references, &branches, &mut errors);
+        (eras, errors)
+    }
+
+    #[test]
+    fn places_eras_on_the_history_containing_their_start() {
+        let (eras, errors) = place(
+            r#"
+            @CoreEvent[big_bang] { }
+            @Event[stars] { }
+            @Timeline[main] { events: [big_bang, stars] }
+            @Era[stellar_era] { start: big_bang end: stars }
+            "#,
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        let interval = eras.iter().next().expect("era is placed");
+        assert_eq!((interval.timeline.name.as_str(), interval.start, interval.end), ("main", 0, 1));
+    }
+
+    #[test]
+    fn reports_eras_that_fit_no_timeline() {
+        let (eras, errors) = place(
+            r#"
+            @CoreEvent[big_bang] { }
+            @Event[stars] { }
+            @Timeline[main] { }
+            @Era[stellar_era] { start: big_bang end: stars }
+            "#,
+        );
+        assert_eq!(eras.iter().count(), 0);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].to_string().contains("Era 'stellar_era' cannot be placed on any timeline"));
+    }
+}
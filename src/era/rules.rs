@@ -0,0 +1,174 @@
+//! Era rule sets
+//!
+//! An era may constrain the events that occur during it:
+//!
+//! ```text
+//! @Era[long_peace] {
+//!     start: treaty
+//!     forbidden_tags: [violence]
+//!     capabilities: [agriculture, writing]
+//!     max_entropy: 0.6
+//! }
+//! ```
+//!
+//! `allowed_tags` and `forbidden_tags` are matched against an event's
+//! `tags`, every entry of an event's `capabilities` must be granted by the
+//! era's `capabilities`, and `min_entropy` / `max_entropy` bound world
+//! entropy once the event has happened, following the Origin's entropy
+//! model (see [`crate::runtime::EntropyModel`]).
+
+use super::Eras;
+use crate::diagnostics::{Label, Severity};
+use crate::error::CldError;
+use crate::resolve::ReferenceGraph;
+use crate::runtime::EntropyModel;
+use crate::timeline::listed_event_span;
+use crate::typed::{TypedEra, TypedEvent, TypedWorld};
+use crate::world::World;
+
+/// The constraints an era places on its events
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EraRules {
+    /// Tags an event may carry; any tag if `None`
+    pub allowed_tags: Option<Vec<String>>,
+    pub forbidden_tags: Vec<String>,
+    /// Capabilities granted to events; any capability if `None`
+    pub capabilities: Option<Vec<String>>,
+    pub min_entropy: Option<f64>,
+    pub max_entropy: Option<f64>,
+}
+
+/// One way an event breaks an era's rules
+#[derive(Debug, Clone, PartialEq)]
+pub enum Breach {
+    /// The event carries a tag outside `allowed_tags`
+    TagNotAllowed(String),
+    /// The event carries a tag listed in `forbidden_tags`
+    ForbiddenTag(String),
+    /// The event needs a capability the era does not grant
+    MissingCapability(String),
+    /// World entropy after the event is below `min_entropy`
+    EntropyBelow { entropy: f64, min: f64 },
+    /// World entropy after the event is above `max_entropy`
+    EntropyAbove { entropy: f64, max: f64 },
+}
+
+impl std::fmt::Display for Breach {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Breach::TagNotAllowed(tag) => write!(f, "tag '{}' is not allowed", tag),
+            Breach::ForbiddenTag(tag) => write!(f, "tag '{}' is forbidden", tag),
+            Breach::MissingCapability(capability) => write!(f, "capability '{}' is not granted", capability),
+            Breach::EntropyBelow { entropy, min } => {
+                write!(f, "entropy {} would fall below the minimum of {}", entropy, min)
+            }
+            Breach::EntropyAbove { entropy, max } => {
+                write!(f, "entropy {} would rise above the maximum of {}", entropy, max)
+            }
+        }
+    }
+}
+
+impl EraRules {
+    /// The rules declared by `era`
+    pub fn of(era: &TypedEra) -> Self {
+        EraRules {
+            allowed_tags: era.allowed_tags.clone(),
+            forbidden_tags: era.forbidden_tags.clone(),
+            capabilities: era.capabilities.clone(),
+            min_entropy: era.min_entropy,
+            max_entropy: era.max_entropy,
+        }
+    }
+
+    /// Whether the era constrains its events at all
+    pub fn is_empty(&self) -> bool {
+        *self == EraRules::default()
+    }
+
+    /// Every way `event` breaks these rules, given world entropy once the
+    /// event has happened
+    pub fn check(&self, event: &TypedEvent, entropy: f64) -> Vec<Breach> {
+        let mut breaches = Vec::new();
+        for tag in &event.tags {
+            if self.forbidden_tags.contains(tag) {
+                breaches.push(Breach::ForbiddenTag(tag.clone()));
+            } else if self.allowed_tags.as_ref().is_some_and(|allowed| !allowed.contains(tag)) {
+                breaches.push(Breach::TagNotAllowed(tag.clone()));
+            }
+        }
+        if let Some(granted) = &self.capabilities {
+            for capability in event.capabilities.iter().filter(|needed| !granted.contains(needed)) {
+                breaches.push(Breach::MissingCapability(capability.clone()));
+            }
+        }
+        if let Some(min) = self.min_entropy.filter(|&min| entropy < min) {
+            breaches.push(Breach::EntropyBelow { entropy, min });
+        }
+        if let Some(max) = self.max_entropy.filter(|&max| entropy > max) {
+            breaches.push(Breach::EntropyAbove { entropy, max });
+        }
+        breaches
+    }
+}
+
+/// Check every event placed in an era against that era's rules
+pub fn validate_era_rules(
+    world: &World,
+    typed: &TypedWorld,
+    references: &ReferenceGraph,
+    eras: &Eras,
+) -> Vec<CldError> {
+    let mut errors = Vec::new();
+    let model = EntropyModel::from_origin(typed.origin.as_ref());
+
+    for interval in eras.iter() {
+        let Some(era) = typed.eras.get(&interval.era.name) else {
+            continue;
+        };
+        let rules = EraRules::of(era);
+        if rules.is_empty() {
+            continue;
+        }
+        let history = eras.history(&interval.timeline);
+        let events: Vec<Option<&TypedEvent>> = history.iter().map(|event| typed.event(event)).collect();
+        // Entropy at each event when the history is run one event per tick
+        let mut running = model.clone();
+        let entropy: Vec<f64> = events
+            .iter()
+            .map(|event| {
+                running.begin_tick();
+                running.apply(event.map_or(0.0, |event| event.entropy_change));
+                running.value()
+            })
+            .collect();
+
+        for position in interval.start..interval.end {
+            let (event, Some(typed_event)) = (&history[position], events[position]) else {
+                continue;
+            };
+            for breach in rules.check(typed_event, entropy[position]) {
+                let span = listed_event_span(references, &interval.timeline, event)
+                    .or_else(|| world.name_span(event));
+                errors.push(CldError::RuleViolation {
+                    rule: "!Era".to_string(),
+                    message: format!(
+                        "Event '{}' breaks the rules of era '{}' on timeline '{}': {}",
+                        event.name, interval.era.name, interval.timeline.name, breach
+                    ),
+                    span,
+                    severity: Severity::Error,
+                    related: world.name_span(&interval.era)
+                        .map(|span| Label {
+                            span,
+                            message: "era rules declared here".to_string(),
+                            primary: false,
+                        })
+                        .into_iter()
+                        .collect(),
+                });
+            }
+        }
+    }
+    errors
+}
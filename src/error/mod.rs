@@ -0,0 +1,402 @@
+//! Structured errors for CLD-OS
+//!
+//! Every problem found while parsing, building or validating a world is a
+//! [`CldError`]. Each variant has a stable error code and a severity, so tools
+//! can filter and count diagnostics without matching on message text.
+//!
+//! | Code    | Variant                 |
+//! |---------|-------------------------|
+//! | `E0001` | `Syntax`                |
+//! | `E0002` | `InvalidValue`          |
+//! | `E0003` | `ImportFailed`          |
+//! | `E0004` | `ImportCycle`           |
+//! | `E0101` | `DuplicateDeclaration`  |
+//! | `W0102` | `DuplicateField`        |
+//! | `E0201` | `UnresolvedReference`   |
+//! | `E0202` | `AmbiguousReference`    |
+//! | `E0301` | `TypeMismatch`          |
+//! | `E0302` | `MissingField`          |
+//! | `W0303` | `UnknownField`          |
+//! | `E0304` | `OutOfRange`            |
+//! | `E0401` | `RuleViolation`         |
+//! | `E0501` | `CausalCycle`           |
+//!
+//! Codes starting with `W` are warnings. Rule violations may be reported
+//! either way, as `E0401` or `W0401`.
+
+use crate::diagnostics::{Diagnostic, Label, Severity};
+use crate::span::Span;
+use serde::{Deserialize, Serialize};
+
+/// An error found in a CLD source
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "error", rename_all = "snake_case")]
+pub enum CldError {
+    /// The source does not match the CLD grammar
+    Syntax { message: String, span: Span },
+    /// A literal matched the grammar but could not be converted to a value
+    InvalidValue { message: String, span: Span },
+    /// An `@Import` names a file that cannot be read
+    ImportFailed {
+        /// The path as written in the import
+        path: String,
+        message: String,
+        span: Span,
+    },
+    /// An `@Import` leads back to a file that is still being loaded
+    ImportCycle {
+        path: String,
+        /// The files of the cycle, starting and ending with the same one
+        chain: Vec<String>,
+        span: Span,
+    },
+    /// A citizen was declared more than once
+    DuplicateDeclaration {
+        kind: String,
+        name: String,
+        span: Span,
+        previous: Span,
+    },
+    /// A citizen sets the same field twice, possibly under different aliases
+    ///
+    /// This is a warning: the later declaration wins.
+    DuplicateField {
+        /// The field, qualified by its citizen, e.g. `Origin[hello].entropy`
+        field: String,
+        /// How the key was spelled at each declaration, in order
+        written_as: Vec<String>,
+        span: Span,
+        previous: Span,
+    },
+    /// An identifier does not name a citizen of the expected kind
+    UnresolvedReference {
+        name: String,
+        expected: String,
+        field: String,
+        span: Span,
+    },
+    /// An identifier names citizens of several acceptable kinds
+    AmbiguousReference {
+        name: String,
+        field: String,
+        /// The matching citizens, e.g. `Event[war]`
+        candidates: Vec<String>,
+        span: Span,
+    },
+    /// Events cause each other in a loop
+    CausalCycle {
+        /// The events of the cycle in causal order, first-declared first
+        events: Vec<String>,
+        /// The edge from the first event to the second
+        span: Span,
+        /// The remaining edges of the cycle
+        related: Vec<Label>,
+    },
+    /// A field holds a value of the wrong type
+    TypeMismatch {
+        field: String,
+        expected: String,
+        found: String,
+        span: Span,
+    },
+    /// A field required by the schema is not set
+    MissingField {
+        field: String,
+        expected: String,
+        span: Span,
+    },
+    /// A field is not declared by the schema for its citizen kind
+    ///
+    /// This is a warning: the field is kept as an extension.
+    UnknownField { field: String, span: Span },
+    /// A number lies outside the range declared by the schema
+    OutOfRange {
+        field: String,
+        value: f64,
+        min: Option<f64>,
+        max: Option<f64>,
+        span: Span,
+    },
+    /// A CLD v6 world rule such as `!Origin` is not satisfied
+    RuleViolation {
+        rule: String,
+        message: String,
+        span: Option<Span>,
+        #[serde(default)]
+        severity: Severity,
+        /// Other locations involved, e.g. a conflicting earlier declaration
+        #[serde(default)]
+        related: Vec<Label>,
+    },
+}
+
+impl CldError {
+    /// Build a rule violation pointing at `span`
+    pub fn rule(rule: impl Into<String>, message: impl Into<String>, span: Option<Span>) -> Self {
+        CldError::RuleViolation {
+            rule: rule.into(),
+            message: message.into(),
+            span,
+            severity: Severity::Error,
+            related: Vec::new(),
+        }
+    }
+
+    /// Build a rule violation that is only reported as a warning
+    pub fn rule_warning(rule: impl Into<String>, message: impl Into<String>, span: Option<Span>) -> Self {
+        CldError::RuleViolation {
+            rule: rule.into(),
+            message: message.into(),
+            span,
+            severity: Severity::Warning,
+            related: Vec::new(),
+        }
+    }
+
+    /// Build a syntax error
+    pub fn syntax(message: impl Into<String>, span: Span) -> Self {
+        CldError::Syntax {
+            message: message.into(),
+            span,
+        }
+    }
+
+    /// Convert a pest parse failure into a syntax error
+    pub fn from_pest<R: pest::RuleType>(error: pest::error::Error<R>) -> Self {
+        let (start, end) = match error.location {
+            pest::error::InputLocation::Pos(pos) => (pos, pos),
+            pest::error::InputLocation::Span(span) => span,
+        };
+        let (line, column) = match error.line_col {
+            pest::error::LineColLocation::Pos(pos) => pos,
+            pest::error::LineColLocation::Span(pos, _) => pos,
+        };
+        CldError::Syntax {
+            message: error.variant.message().to_string(),
+            span: Span {
+                file: 0,
+                start,
+                end,
+                line: line as u32,
+                column: column as u32,
+            },
+        }
+    }
+
+    /// Stable error code, e.g. `E0201`
+    pub fn code(&self) -> &'static str {
+        match self {
+            CldError::Syntax { .. } => "E0001",
+            CldError::InvalidValue { .. } => "E0002",
+            CldError::ImportFailed { .. } => "E0003",
+            CldError::ImportCycle { .. } => "E0004",
+            CldError::DuplicateDeclaration { .. } => "E0101",
+            CldError::DuplicateField { .. } => "W0102",
+            CldError::UnresolvedReference { .. } => "E0201",
+            CldError::AmbiguousReference { .. } => "E0202",
+            CldError::TypeMismatch { .. } => "E0301",
+            CldError::MissingField { .. } => "E0302",
+            CldError::UnknownField { .. } => "W0303",
+            CldError::OutOfRange { .. } => "E0304",
+            CldError::RuleViolation {
+                severity: Severity::Error,
+                ..
+            } => "E0401",
+            CldError::RuleViolation { .. } => "W0401",
+            CldError::CausalCycle { .. } => "E0501",
+        }
+    }
+
+    /// How serious the error is
+    pub fn severity(&self) -> Severity {
+        match self {
+            CldError::RuleViolation { severity, .. } => *severity,
+            CldError::DuplicateField { .. } | CldError::UnknownField { .. } => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+
+    /// Whether this should fail validation, as opposed to being a warning
+    pub fn is_error(&self) -> bool {
+        self.severity() == Severity::Error
+    }
+
+    /// The main source location of the error, if it has one
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            CldError::Syntax { span, .. }
+            | CldError::InvalidValue { span, .. }
+            | CldError::ImportFailed { span, .. }
+            | CldError::ImportCycle { span, .. }
+            | CldError::DuplicateDeclaration { span, .. }
+            | CldError::DuplicateField { span, .. }
+            | CldError::UnresolvedReference { span, .. }
+            | CldError::AmbiguousReference { span, .. }
+            | CldError::TypeMismatch { span, .. }
+            | CldError::MissingField { span, .. }
+            | CldError::UnknownField { span, .. }
+            | CldError::OutOfRange { span, .. }
+            | CldError::CausalCycle { span, .. } => Some(*span),
+            CldError::RuleViolation { span, .. } => *span,
+        }
+    }
+
+    /// Point every span of the error into file `file` of a source map
+    ///
+    /// Used for errors found while parsing a single file of a multi-file
+    /// world, whose spans are relative to that file.
+    pub fn in_file(mut self, file: usize) -> Self {
+        match &mut self {
+            CldError::Syntax { span, .. }
+            | CldError::InvalidValue { span, .. }
+            | CldError::ImportFailed { span, .. }
+            | CldError::ImportCycle { span, .. }
+            | CldError::UnresolvedReference { span, .. }
+            | CldError::AmbiguousReference { span, .. }
+            | CldError::TypeMismatch { span, .. }
+            | CldError::MissingField { span, .. }
+            | CldError::UnknownField { span, .. }
+            | CldError::OutOfRange { span, .. } => span.file = file,
+            CldError::DuplicateDeclaration { span, previous, .. }
+            | CldError::DuplicateField { span, previous, .. } => {
+                span.file = file;
+                previous.file = file;
+            }
+            CldError::RuleViolation { span, related, .. } => {
+                if let Some(span) = span {
+                    span.file = file;
+                }
+                for label in related {
+                    label.span.file = file;
+                }
+            }
+            CldError::CausalCycle { span, related, .. } => {
+                span.file = file;
+                for label in related {
+                    label.span.file = file;
+                }
+            }
+        }
+        self
+    }
+
+    /// Convert to a renderable diagnostic with labels pointing at the source
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::new(self.severity(), self.to_string()).with_code(self.code());
+        match self {
+            CldError::Syntax { message, span } => diagnostic.with_label(*span, message.clone()),
+            CldError::InvalidValue { span, .. } => diagnostic.with_label(*span, "invalid literal"),
+            CldError::ImportFailed { span, .. } => diagnostic.with_label(*span, "imported here"),
+            CldError::ImportCycle { span, .. } => diagnostic.with_label(*span, "imported here"),
+            CldError::DuplicateDeclaration {
+                kind,
+                span,
+                previous,
+                ..
+            } => diagnostic
+                .with_label(*span, "declared again here")
+                .with_secondary_label(*previous, format!("first @{} declared here", kind))
+                .with_note("write `merge` or `override` after the name to layer on the first declaration"),
+            CldError::DuplicateField { span, previous, .. } => diagnostic
+                .with_label(*span, "set again here")
+                .with_secondary_label(*previous, "first set here")
+                .with_note("the later value is used"),
+            CldError::UnresolvedReference { expected, span, .. } => {
+                diagnostic.with_label(*span, format!("no {} with this name", expected))
+            }
+            CldError::AmbiguousReference { candidates, span, .. } => diagnostic
+                .with_label(*span, "ambiguous name")
+                .with_note(format!("candidates: {}", candidates.join(", "))),
+            CldError::TypeMismatch { expected, found, span, .. } => {
+                diagnostic.with_label(*span, format!("expected {}, found {}", expected, found))
+            }
+            CldError::MissingField { expected, span, .. } => {
+                diagnostic.with_label(*span, format!("needs {}", expected))
+            }
+            CldError::UnknownField { span, .. } => diagnostic
+                .with_label(*span, "not declared by the schema")
+                .with_note("declare it with @Schema if it is intentional"),
+            CldError::OutOfRange { span, .. } => diagnostic.with_label(*span, "out of range"),
+            CldError::RuleViolation {
+                rule,
+                span,
+                related,
+                ..
+            } => {
+                let mut diagnostic = match span {
+                    Some(span) => diagnostic.with_label(*span, ""),
+                    None => diagnostic,
+                };
+                diagnostic.labels.extend(related.iter().cloned());
+                diagnostic
+                    .with_rule(rule.clone())
+                    .with_note(format!("required by the {} rule", rule))
+            }
+            CldError::CausalCycle { events, span, related } => {
+                let message = match events.get(1) {
+                    Some(next) => format!("{} causes {}", events[0], next),
+                    None => format!("{} causes itself", events[0]),
+                };
+                let mut diagnostic = diagnostic.with_label(*span, message);
+                diagnostic.labels.extend(related.iter().cloned());
+                diagnostic.with_note("an event cannot be its own cause")
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for CldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CldError::Syntax { message, .. } => write!(f, "syntax error: {}", message),
+            CldError::InvalidValue { message, .. } => write!(f, "{}", message),
+            CldError::ImportFailed { path, message, .. } => {
+                write!(f, "cannot import '{}': {}", path, message)
+            }
+            CldError::ImportCycle { chain, .. } => write!(f, "import cycle: {}", chain.join(" -> ")),
+            CldError::DuplicateDeclaration { kind, name, .. } => {
+                write!(f, "@{} '{}' is declared more than once", kind, name)
+            }
+            CldError::DuplicateField { field, written_as, .. } => {
+                if written_as.windows(2).all(|pair| pair[0] == pair[1]) {
+                    write!(f, "{} is set more than once", field)
+                } else {
+                    write!(f, "{} is set under both aliases '{}'", field, written_as.join("' and '"))
+                }
+            }
+            CldError::UnresolvedReference {
+                name,
+                expected,
+                field,
+                ..
+            } => write!(f, "{} '{}' referenced in {} is not defined", expected, name, field),
+            CldError::AmbiguousReference { name, field, .. } => {
+                write!(f, "'{}' referenced in {} is ambiguous", name, field)
+            }
+            CldError::TypeMismatch {
+                field,
+                expected,
+                found,
+                ..
+            } => write!(f, "{} must be {}, found {}", field, expected, found),
+            CldError::MissingField { field, .. } => write!(f, "{} is required", field),
+            CldError::UnknownField { field, .. } => write!(f, "{} is not a known field", field),
+            CldError::OutOfRange {
+                field,
+                value,
+                min,
+                max,
+                ..
+            } => {
+                let bound = |bound: &Option<f64>| bound.map(|b| b.to_string()).unwrap_or_default();
+                write!(f, "{} is {}, outside {}..{}", field, value, bound(min), bound(max))
+            }
+            CldError::RuleViolation { rule, message, .. } => write!(f, "{}: {}", rule, message),
+            CldError::CausalCycle { events, .. } => {
+                write!(f, "causal cycle: {} -> {}", events.join(" -> "), events[0])
+            }
+        }
+    }
+}
+
+impl std::error::Error for CldError {}
@@ -0,0 +1,629 @@
+//! Type checking of `requires` and `effects`
+//!
+//! Variables get their types from the `effects` that set them: a number
+//! makes a number variable, `true`/`false` a boolean and an identifier a
+//! name. A variable set only by expressions takes the type of those
+//! expressions, which may refer to each other in any order as long as some
+//! type can be inferred.
+
+use super::{parse, BinaryOp, Expr, ExprKind, ExprValue, Type, UnaryOp};
+use crate::citizens::{namespace_of, CitizenData, Value};
+use crate::error::CldError;
+use crate::schema::CITIZEN_KINDS;
+use crate::span::Span;
+use crate::world::World;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Names every expression can read, and their types
+const BUILTINS: &[(&str, Type)] = &[("entropy", Type::Number), ("tick", Type::Number), ("era", Type::Symbol)];
+
+/// One entry of an event's `effects`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Effect {
+    /// A number, added to the variable
+    Add(f64),
+    /// An expression, whose value replaces the variable
+    Assign(Expr),
+    /// Any other value, which replaces the variable
+    Set(Value),
+}
+
+/// The variables of a world and their types
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Variables {
+    types: BTreeMap<String, Type>,
+    /// Identifiers assigned by `effects`, usable as names in expressions
+    symbols: BTreeSet<String>,
+}
+
+impl Variables {
+    /// Infer the type of every variable set by an event's `effects`
+    ///
+    /// Returns the problems found along the way: assignments to built-ins,
+    /// conflicting literal types and variables whose type cannot be
+    /// inferred. Problems inside expressions are left to
+    /// [`validate_expressions`].
+    pub fn infer(world: &World) -> (Self, Vec<CldError>) {
+        let mut variables = Variables::default();
+        let mut errors = Vec::new();
+        let mut pending: Vec<(&dyn CitizenData, String, Expr)> = Vec::new();
+
+        for event in events(world) {
+            let Some(effects) = event.fields().get("effects").and_then(Value::as_map) else {
+                continue;
+            };
+            for effect in effects.iter() {
+                if BUILTINS.iter().any(|(name, _)| *name == effect.key) {
+                    errors.push(CldError::rule(
+                        "!Effect",
+                        format!(
+                            "Event '{}' cannot assign the built-in `{}`{}",
+                            event.name(),
+                            effect.key,
+                            if effect.key == "entropy" { "; use entropy_change" } else { "" }
+                        ),
+                        Some(effect.key_span),
+                    ));
+                    continue;
+                }
+                let ty = match &effect.value.node {
+                    Value::Number(_) => Type::Number,
+                    Value::Boolean(_) => Type::Boolean,
+                    Value::Identifier(name) => {
+                        variables.symbols.insert(name.clone());
+                        Type::Symbol
+                    }
+                    Value::String(text) => {
+                        // Syntax errors are reported by validate_expressions
+                        if let Ok(expr) = parse(text) {
+                            pending.push((event, effect.key.clone(), expr));
+                        }
+                        continue;
+                    }
+                    Value::List(_) | Value::Map(_) => continue,
+                };
+                match variables.types.get(&effect.key) {
+                    Some(&declared) if declared != ty => errors.push(CldError::TypeMismatch {
+                        field: format!("{}[{}].effects.{}", event.kind(), event.name(), effect.key),
+                        expected: declared.to_string(),
+                        found: ty.to_string(),
+                        span: effect.value.span,
+                    }),
+                    Some(_) => {}
+                    None => {
+                        variables.types.insert(effect.key.clone(), ty);
+                    }
+                }
+            }
+        }
+
+        // Type the variables set only by expressions, until nothing changes
+        let targets: BTreeSet<String> = pending.iter().map(|(_, key, _)| key.clone()).collect();
+        loop {
+            let mut progress = false;
+            for (event, key, expr) in &mut pending {
+                if variables.types.contains_key(key.as_str()) {
+                    continue;
+                }
+                let checker = Checker {
+                    world,
+                    namespace: namespace_of(event.name()),
+                    variables: &variables,
+                    pending: &targets,
+                };
+                if let Some(ty) = checker.infer(expr, &mut Vec::new()) {
+                    variables.types.insert(key.clone(), ty);
+                    progress = true;
+                }
+            }
+            if !progress {
+                break;
+            }
+        }
+        for (event, key, _) in &pending {
+            if variables.types.contains_key(key.as_str()) {
+                continue;
+            }
+            let span = event
+                .fields()
+                .get("effects")
+                .and_then(Value::as_map)
+                .and_then(|effects| effects.field(key))
+                .map(|field| field.key_span);
+            errors.push(CldError::rule(
+                "!Effect",
+                format!(
+                    "Cannot infer the type of `{}`; set it to a number, boolean or identifier in some event's effects",
+                    key
+                ),
+                span,
+            ));
+            // Report each variable once
+            variables.types.insert(key.clone(), Type::Number);
+        }
+
+        (variables, errors)
+    }
+
+    /// The type of the variable or built-in called `name`
+    pub fn get(&self, name: &str) -> Option<Type> {
+        BUILTINS
+            .iter()
+            .find(|(builtin, _)| *builtin == name)
+            .map(|(_, ty)| *ty)
+            .or_else(|| self.types.get(name).copied())
+    }
+
+    /// The value of `name` before any event has set it
+    pub fn initial(&self, name: &str) -> Option<ExprValue> {
+        self.types.get(name).map(|&ty| ExprValue::default_of(ty))
+    }
+}
+
+/// Every `@Event` and `@CoreEvent`, in declaration order
+fn events(world: &World) -> impl Iterator<Item = &dyn CitizenData> {
+    world
+        .declaration_order()
+        .iter()
+        .filter(|citizen| citizen.kind == "Event" || citizen.kind == "CoreEvent")
+        .filter_map(|citizen| world.citizen(&citizen.kind, &citizen.name))
+}
+
+/// A problem found in an expression, at byte offsets into its text
+struct Problem {
+    error: ProblemKind,
+    start: usize,
+    end: usize,
+}
+
+enum ProblemKind {
+    /// A name that is not a variable, built-in or citizen
+    UnknownName(String),
+    /// A `happened` argument that names no event
+    UnknownEvent(String),
+    /// A `happened` argument that names both an Event and a CoreEvent
+    AmbiguousEvent { name: String, candidates: Vec<String> },
+    Mismatch { expected: String, found: Type },
+    Other(String),
+}
+
+/// Infers types, binding citizen names to the citizens they refer to
+struct Checker<'a> {
+    world: &'a World,
+    /// Namespace of the event the expression belongs to
+    namespace: &'a str,
+    variables: &'a Variables,
+    /// Variables whose type is still being inferred
+    pending: &'a BTreeSet<String>,
+}
+
+impl Checker<'_> {
+    /// The type of `expr`, or `None` if it cannot be known
+    fn infer(&self, expr: &mut Expr, problems: &mut Vec<Problem>) -> Option<Type> {
+        let (start, end) = (expr.start, expr.end);
+        match &mut expr.kind {
+            ExprKind::Number(_) => Some(Type::Number),
+            ExprKind::Boolean(_) => Some(Type::Boolean),
+            ExprKind::Text(_) => Some(Type::Text),
+            ExprKind::Event(_) => Some(Type::Symbol),
+            ExprKind::Name(name) => {
+                if let Some(ty) = self.variables.get(name) {
+                    return Some(ty);
+                }
+                if self.pending.contains(name.as_str()) {
+                    return None;
+                }
+                let kinds: Vec<String> = CITIZEN_KINDS.iter().map(|kind| kind.to_string()).collect();
+                if let [citizen] = self.world.lookup_in(self.namespace, name, &kinds).as_slice() {
+                    *name = citizen.name.clone();
+                    return Some(Type::Symbol);
+                }
+                if self.variables.symbols.contains(name.as_str()) {
+                    return Some(Type::Symbol);
+                }
+                problems.push(Problem {
+                    error: ProblemKind::UnknownName(name.clone()),
+                    start,
+                    end,
+                });
+                None
+            }
+            ExprKind::Call { function, args } => {
+                if function != "happened" || args.len() != 1 {
+                    problems.push(Problem {
+                        error: ProblemKind::Other(format!(
+                            "unknown function `{}` with {} argument{}; expected `happened(event)`",
+                            function,
+                            args.len(),
+                            if args.len() == 1 { "" } else { "s" }
+                        )),
+                        start,
+                        end,
+                    });
+                    return Some(Type::Boolean);
+                }
+                let arg = &mut args[0];
+                let (arg_start, arg_end) = (arg.start, arg.end);
+                let ExprKind::Name(event) = &mut arg.kind else {
+                    problems.push(Problem {
+                        error: ProblemKind::Other("`happened` takes the name of an event".to_string()),
+                        start: arg_start,
+                        end: arg_end,
+                    });
+                    return Some(Type::Boolean);
+                };
+                let kinds = ["Event".to_string(), "CoreEvent".to_string()];
+                let error = match self.world.lookup_in(self.namespace, event, &kinds).as_slice() {
+                    [found] => {
+                        arg.kind = ExprKind::Event(found.clone());
+                        return Some(Type::Boolean);
+                    }
+                    [] => ProblemKind::UnknownEvent(event.clone()),
+                    several => ProblemKind::AmbiguousEvent {
+                        name: event.clone(),
+                        candidates: several.iter().map(ToString::to_string).collect(),
+                    },
+                };
+                problems.push(Problem {
+                    error,
+                    start: arg_start,
+                    end: arg_end,
+                });
+                Some(Type::Boolean)
+            }
+            ExprKind::Unary { op, operand } => {
+                let expected = match op {
+                    UnaryOp::Not => Type::Boolean,
+                    UnaryOp::Neg => Type::Number,
+                };
+                self.expect(operand, expected, problems);
+                Some(expected)
+            }
+            ExprKind::Binary { op, left, right } => match op {
+                BinaryOp::Or | BinaryOp::And => {
+                    self.expect(left, Type::Boolean, problems);
+                    self.expect(right, Type::Boolean, problems);
+                    Some(Type::Boolean)
+                }
+                BinaryOp::Eq | BinaryOp::Ne => {
+                    if let Some(ty) = self.infer(left, problems) {
+                        self.expect(right, ty, problems);
+                    } else {
+                        self.infer(right, problems);
+                    }
+                    Some(Type::Boolean)
+                }
+                BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => {
+                    self.expect(left, Type::Number, problems);
+                    self.expect(right, Type::Number, problems);
+                    Some(Type::Boolean)
+                }
+                BinaryOp::Add => {
+                    // Numbers add and strings concatenate
+                    let ty = match self.infer(left, problems) {
+                        Some(ty @ (Type::Number | Type::Text)) => {
+                            self.expect(right, ty, problems);
+                            return Some(ty);
+                        }
+                        Some(found) => {
+                            problems.push(mismatch("a number or string", found, left));
+                            return Some(Type::Number);
+                        }
+                        None => self.infer(right, problems),
+                    };
+                    match ty {
+                        Some(Type::Number | Type::Text) | None => ty,
+                        Some(found) => {
+                            problems.push(mismatch("a number or string", found, right));
+                            Some(Type::Number)
+                        }
+                    }
+                }
+                BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div => {
+                    self.expect(left, Type::Number, problems);
+                    self.expect(right, Type::Number, problems);
+                    Some(Type::Number)
+                }
+            },
+        }
+    }
+
+    fn expect(&self, expr: &mut Expr, expected: Type, problems: &mut Vec<Problem>) {
+        match self.infer(expr, problems) {
+            Some(found) if found != expected => problems.push(mismatch(&expected.to_string(), found, expr)),
+            _ => {}
+        }
+    }
+}
+
+fn mismatch(expected: &str, found: Type, expr: &Expr) -> Problem {
+    Problem {
+        error: ProblemKind::Mismatch {
+            expected: expected.to_string(),
+            found,
+        },
+        start: expr.start,
+        end: expr.end,
+    }
+}
+
+/// Parse and check the expression `text`, written in `field` at `span`
+///
+/// Returns the bound expression and its type, or the errors found.
+fn check_expression(
+    world: &World,
+    variables: &Variables,
+    event: &dyn CitizenData,
+    field: &str,
+    text: &str,
+    span: Span,
+) -> Result<(Expr, Type), Vec<CldError>> {
+    let mut expr = parse(text).map_err(|e| {
+        let at = e.span().unwrap_or_default();
+        let message = match &e {
+            CldError::Syntax { message, .. } => format!("in expression: {}", message),
+            other => other.to_string(),
+        };
+        vec![CldError::syntax(message, locate(span, text, at.start, at.end))]
+    })?;
+
+    let checker = Checker {
+        world,
+        namespace: namespace_of(event.name()),
+        variables,
+        pending: &BTreeSet::new(),
+    };
+    let mut problems = Vec::new();
+    let ty = checker.infer(&mut expr, &mut problems);
+    let qualified = format!("{}[{}].{}", event.kind(), event.name(), field);
+    let errors: Vec<CldError> = problems
+        .into_iter()
+        .map(|problem| {
+            let span = locate(span, text, problem.start, problem.end);
+            match problem.error {
+                ProblemKind::UnknownName(name) => CldError::rule(
+                    "!Expression",
+                    format!(
+                        "`{}` in {} is not a variable, built-in or citizen",
+                        name, qualified
+                    ),
+                    Some(span),
+                ),
+                ProblemKind::UnknownEvent(name) => CldError::UnresolvedReference {
+                    name,
+                    expected: "@Event or @CoreEvent".to_string(),
+                    field: qualified.clone(),
+                    span,
+                },
+                ProblemKind::AmbiguousEvent { name, candidates } => CldError::AmbiguousReference {
+                    name,
+                    field: qualified.clone(),
+                    candidates,
+                    span,
+                },
+                ProblemKind::Mismatch { expected, found } => CldError::TypeMismatch {
+                    field: format!("`{}` in {}", &text[problem.start..problem.end], qualified),
+                    expected,
+                    found: found.to_string(),
+                    span,
+                },
+                ProblemKind::Other(message) => CldError::rule("!Expression", message, Some(span)),
+            }
+        })
+        .collect();
+
+    match ty {
+        Some(ty) if errors.is_empty() => Ok((expr, ty)),
+        _ => Err(errors),
+    }
+}
+
+/// Place the byte range `start..end` of an expression in the file
+///
+/// `span` is the span of the string literal holding the expression. The
+/// range can only be placed exactly when the literal is a plain one-line
+/// `"..."` without escapes; otherwise the whole literal is used.
+pub fn locate(span: Span, text: &str, start: usize, end: usize) -> Span {
+    let plain = span.end - span.start == text.len() + 2 && !text.contains('\n');
+    if !plain || end > text.len() {
+        return span;
+    }
+    Span {
+        start: span.start + 1 + start,
+        end: span.start + 1 + end,
+        column: span.column + 1 + text[..start].chars().count() as u32,
+        ..span
+    }
+}
+
+/// Check every event's `requires` and `effects` expressions
+///
+/// `requires` must be a boolean expression, and each expression in
+/// `effects` must have the type of the variable it assigns.
+pub fn validate_expressions(world: &World) -> Vec<CldError> {
+    let (variables, mut errors) = Variables::infer(world);
+
+    for event in events(world) {
+        if let Some(field) = event.fields().field("requires") {
+            if let Value::String(text) = &field.value.node {
+                match check_expression(world, &variables, event, "requires", text, field.value.span) {
+                    Ok((_, Type::Boolean)) => {}
+                    Ok((_, found)) => errors.push(CldError::TypeMismatch {
+                        field: format!("{}[{}].requires", event.kind(), event.name()),
+                        expected: Type::Boolean.to_string(),
+                        found: found.to_string(),
+                        span: field.value.span,
+                    }),
+                    Err(problems) => errors.extend(problems),
+                }
+            }
+        }
+
+        let Some(effects) = event.fields().get("effects").and_then(Value::as_map) else {
+            continue;
+        };
+        for effect in effects.iter() {
+            let Value::String(text) = &effect.value.node else {
+                continue;
+            };
+            let field = format!("effects.{}", effect.key);
+            match check_expression(world, &variables, event, &field, text, effect.value.span) {
+                Ok((_, found)) => match variables.get(&effect.key) {
+                    Some(expected) if expected != found => errors.push(CldError::TypeMismatch {
+                        field: format!("{}[{}].{}", event.kind(), event.name(), field),
+                        expected: expected.to_string(),
+                        found: found.to_string(),
+                        span: effect.value.span,
+                    }),
+                    _ => {}
+                },
+                Err(problems) => errors.extend(problems),
+            }
+        }
+    }
+    errors
+}
+
+/// The `requires` expression of `event`, ready to evaluate
+///
+/// Returns `None` if the event has none, or if it does not check.
+pub fn compile_requires(world: &World, variables: &Variables, event: &dyn CitizenData) -> Option<Expr> {
+    let field = event.fields().field("requires")?;
+    let text = field.value.as_string()?;
+    check_expression(world, variables, event, "requires", text, field.value.span)
+        .ok()
+        .map(|(expr, _)| expr)
+}
+
+/// The `effects` of `event`, in order, ready to apply
+///
+/// Expressions that do not check are left out.
+pub fn compile_effects(world: &World, variables: &Variables, event: &dyn CitizenData) -> Vec<(String, Effect)> {
+    let Some(effects) = event.fields().get("effects").and_then(Value::as_map) else {
+        return Vec::new();
+    };
+    effects
+        .iter()
+        .filter_map(|effect| {
+            let compiled = match &effect.value.node {
+                Value::Number(number) => Effect::Add(*number),
+                Value::String(text) => {
+                    let field = format!("effects.{}", effect.key);
+                    let (expr, _) =
+                        check_expression(world, variables, event, &field, text, effect.value.span).ok()?;
+                    Effect::Assign(expr)
+                }
+                other => Effect::Set(other.clone()),
+            };
+            Some((effect.key.clone(), compiled))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::CLDParser;
+
+    fn world(source: &str) -> World {
+        let document = CLDParser::parse_document(source).expect("test source parses");
+        let (world, errors) = World::build_document(document);
+        assert!(errors.is_empty(), "{:?}", errors);
+        world
+    }
+
+    /// Check the `requires` of `Event[e]`, declared after `declarations`
+    fn check(declarations: &str, requires: &str) -> Result<(Expr, Type), Vec<CldError>> {
+        let source = format!("{}\n@Event[e] {{ requires: \"{}\" }}", declarations, requires);
+        let world = world(&source);
+        let (variables, errors) = Variables::infer(&world);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let event = world.citizen("Event", "e").expect("test event is declared");
+        let field = event.fields().field("requires").expect("test event requires");
+        check_expression(&world, &variables, event, "requires", requires, field.value.span)
+    }
+
+    /// The column of the last `fragment` in the `requires` of `Event[e]`
+    fn column(requires: &str, fragment: &str) -> u32 {
+        let prefix = "@Event[e] { requires: \"";
+        (prefix.len() + requires.rfind(fragment).expect("fragment is in the expression") + 1) as u32
+    }
+
+    #[test]
+    fn infers_types_from_variables_builtins_and_citizens() {
+        let declarations = "@CoreEvent[big_bang] { effects: { stars: 10, mood: calm } }";
+        let (_, ty) = check(declarations, "stars > 5 && mood == calm && era == big_bang").expect("checks");
+        assert_eq!(ty, Type::Boolean);
+        let (_, ty) = check(declarations, "stars * entropy + tick").expect("checks");
+        assert_eq!(ty, Type::Number);
+        let (_, ty) = check(declarations, "'stars: ' + 'many'").expect("checks");
+        assert_eq!(ty, Type::Text);
+    }
+
+    #[test]
+    fn reports_mismatches_at_the_offending_operand() {
+        let declarations = "@CoreEvent[big_bang] { effects: { stars: 10 } }";
+        let requires = "stars > 5 && stars";
+        let errors = check(declarations, requires).expect_err("does not check");
+        let [CldError::TypeMismatch { expected, found, span, .. }] = &errors[..] else {
+            panic!("expected one type mismatch, got {:?}", errors);
+        };
+        assert_eq!((expected.as_str(), found.as_str()), ("a boolean", "a number"));
+        assert_eq!(span.column, column(requires, "stars"));
+        assert_eq!(span.end - span.start, 5);
+    }
+
+    #[test]
+    fn reports_unknown_names_and_events() {
+        let requires = "happened(dawn) || nothing";
+        let errors = check("@Event[night] { }", requires).expect_err("does not check");
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert!(
+            matches!(&errors[0], CldError::UnresolvedReference { name, expected, span, .. }
+                if name == "dawn" && expected == "@Event or @CoreEvent" && span.column == column(requires, "dawn")),
+            "{:?}",
+            errors[0]
+        );
+        assert!(
+            matches!(&errors[1], CldError::RuleViolation { rule, span: Some(span), .. }
+                if rule == "!Expression" && span.column == column(requires, "nothing")),
+            "{:?}",
+            errors[1]
+        );
+    }
+
+    #[test]
+    fn binds_happened_to_the_event_it_names() {
+        let (expr, _) = check("@CoreEvent[dawn] { }", "happened(dawn)").expect("checks");
+        let ExprKind::Call { args, .. } = &expr.kind else {
+            panic!("expected a call, got {:?}", expr);
+        };
+        assert!(
+            matches!(&args[0].kind, ExprKind::Event(event) if event.kind == "CoreEvent" && event.name == "dawn"),
+            "{:?}",
+            args[0]
+        );
+    }
+
+    #[test]
+    fn reports_events_named_by_both_kinds() {
+        let requires = "happened(war)";
+        let errors = check("@Event[war] { }\n@CoreEvent[war] { }", requires).expect_err("does not check");
+        let [CldError::AmbiguousReference { name, candidates, span, .. }] = &errors[..] else {
+            panic!("expected one ambiguous reference, got {:?}", errors);
+        };
+        assert_eq!(name, "war");
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(span.column, column(requires, "war"));
+    }
+
+    #[test]
+    fn reports_syntax_errors_in_the_file() {
+        let requires = "tick > > 2";
+        let errors = check("", requires).expect_err("does not parse");
+        let [CldError::Syntax { span, .. }] = &errors[..] else {
+            panic!("expected one syntax error, got {:?}", errors);
+        };
+        assert_eq!(span.column, column(requires, "> 2"));
+    }
+}
@@ -0,0 +1,526 @@
+//! Event preconditions and effects
+//!
+//! An event may say when it can fire with a `requires` expression, and
+//! compute its `effects` with expressions:
+//!
+//! ```text
+//! @Event[first_cities] {
+//!     requires: "entropy < 0.7 && happened(farming) && era == bronze_age"
+//!     effects: { population: "population * 2", capital: ur }
+//! }
+//! ```
+//!
+//! Expressions have numbers, booleans, `'strings'` and names. A name is a
+//! variable set by some event's `effects`, one of the built-ins `entropy`,
+//! `tick` and `era`, or else the name of a citizen (or of an identifier
+//! assigned by `effects`). `happened(event)` tells whether an event has
+//! fired. Operators, from lowest to highest precedence, are `||`, `&&`,
+//! `==` `!=`, `<` `<=` `>` `>=`, `+` `-`, `*` `/`, and prefix `!` and `-`.
+//!
+//! Expressions are type-checked against the world before a run (see
+//! [`validate_expressions`]), so evaluating them fails only on division by
+//! zero.
+
+use crate::citizens::Value;
+use crate::error::CldError;
+use crate::resolve::CitizenRef;
+use pest::iterators::Pairs;
+use pest::pratt_parser::{Assoc, Op, PrattParser};
+use pest::Parser as PestParser;
+use pest_derive::Parser;
+use std::sync::OnceLock;
+
+mod check;
+
+pub use check::{compile_effects, compile_requires, locate, validate_expressions, Effect, Variables};
+
+#[derive(Parser)]
+#[grammar = "parser/grammar/expr.pest"]
+struct ExprParser;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Not,
+    Neg,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl BinaryOp {
+    /// The operator as written
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Or => "||",
+            BinaryOp::And => "&&",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Number(f64),
+    Boolean(bool),
+    Text(String),
+    /// A variable, built-in or citizen name
+    Name(String),
+    /// The argument of `happened`, once the checker has bound it to an event
+    Event(CitizenRef),
+    Call { function: String, args: Vec<Expr> },
+    Unary { op: UnaryOp, operand: Box<Expr> },
+    Binary { op: BinaryOp, left: Box<Expr>, right: Box<Expr> },
+}
+
+/// A parsed expression
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    /// Byte offsets into the expression's source text
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The type of an expression
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Number,
+    Boolean,
+    Text,
+    /// A citizen name or other identifier
+    Symbol,
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Type::Number => write!(f, "a number"),
+            Type::Boolean => write!(f, "a boolean"),
+            Type::Text => write!(f, "a string"),
+            Type::Symbol => write!(f, "a name"),
+        }
+    }
+}
+
+/// The value of an expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprValue {
+    Number(f64),
+    Boolean(bool),
+    Text(String),
+    /// A name; `None` stands for no name, e.g. `era` outside any era
+    Symbol(Option<String>),
+}
+
+impl ExprValue {
+    pub fn ty(&self) -> Type {
+        match self {
+            ExprValue::Number(_) => Type::Number,
+            ExprValue::Boolean(_) => Type::Boolean,
+            ExprValue::Text(_) => Type::Text,
+            ExprValue::Symbol(_) => Type::Symbol,
+        }
+    }
+
+    /// The value of a variable as stored in the world state
+    ///
+    /// Lists and maps have no expression value.
+    pub fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Number(number) => Some(ExprValue::Number(*number)),
+            Value::Boolean(boolean) => Some(ExprValue::Boolean(*boolean)),
+            Value::String(text) => Some(ExprValue::Text(text.clone())),
+            Value::Identifier(name) => Some(ExprValue::Symbol(Some(name.clone()))),
+            Value::List(_) | Value::Map(_) => None,
+        }
+    }
+
+    /// The value to store in the world state; `None` for no name
+    pub fn to_value(&self) -> Option<Value> {
+        match self {
+            ExprValue::Number(number) => Some(Value::Number(*number)),
+            ExprValue::Boolean(boolean) => Some(Value::Boolean(*boolean)),
+            ExprValue::Text(text) => Some(Value::String(text.clone())),
+            ExprValue::Symbol(name) => name.clone().map(Value::Identifier),
+        }
+    }
+
+    /// The value of a variable of type `ty` that has not been set yet
+    pub fn default_of(ty: Type) -> Self {
+        match ty {
+            Type::Number => ExprValue::Number(0.0),
+            Type::Boolean => ExprValue::Boolean(false),
+            Type::Text => ExprValue::Text(String::new()),
+            Type::Symbol => ExprValue::Symbol(None),
+        }
+    }
+}
+
+/// What an expression can see of the world while it is evaluated
+pub trait Env {
+    /// The value of a variable or built-in, or `None` if `name` is neither
+    fn variable(&self, name: &str) -> Option<ExprValue>;
+
+    /// Whether `event` has fired
+    fn happened(&self, event: &CitizenRef) -> bool;
+}
+
+/// Parse `source` as an expression
+///
+/// Syntax errors have spans relative to `source`; see [`locate`] to place
+/// them in the file.
+pub fn parse(source: &str) -> Result<Expr, CldError> {
+    let mut pairs = ExprParser::parse(Rule::expression, source)
+        .map_err(|e| CldError::from_pest(e.renamed_rules(describe)))?;
+    let expression = pairs.next().expect("the expression rule always matches once");
+    let expr = expression.into_inner().next().expect("an expression holds one expr");
+    Ok(parse_expr(expr.into_inner()))
+}
+
+/// How a rule is named in syntax errors
+fn describe(rule: &Rule) -> String {
+    match rule {
+        Rule::not => "`!`",
+        Rule::neg => "`-`",
+        Rule::or => "`||`",
+        Rule::and => "`&&`",
+        Rule::eq | Rule::ne => "a comparison",
+        Rule::lt | Rule::le | Rule::gt | Rule::ge => "a comparison",
+        Rule::add | Rule::sub | Rule::mul | Rule::div => "an operator",
+        Rule::number => "a number",
+        Rule::boolean => "a boolean",
+        Rule::string => "a string",
+        Rule::name => "a name",
+        Rule::EOI => "the end of the expression",
+        _ => "an expression",
+    }
+    .to_string()
+}
+
+fn pratt() -> &'static PrattParser<Rule> {
+    static PRATT: OnceLock<PrattParser<Rule>> = OnceLock::new();
+    PRATT.get_or_init(|| {
+        PrattParser::new()
+            .op(Op::infix(Rule::or, Assoc::Left))
+            .op(Op::infix(Rule::and, Assoc::Left))
+            .op(Op::infix(Rule::eq, Assoc::Left) | Op::infix(Rule::ne, Assoc::Left))
+            .op(Op::infix(Rule::lt, Assoc::Left)
+                | Op::infix(Rule::le, Assoc::Left)
+                | Op::infix(Rule::gt, Assoc::Left)
+                | Op::infix(Rule::ge, Assoc::Left))
+            .op(Op::infix(Rule::add, Assoc::Left) | Op::infix(Rule::sub, Assoc::Left))
+            .op(Op::infix(Rule::mul, Assoc::Left) | Op::infix(Rule::div, Assoc::Left))
+            .op(Op::prefix(Rule::not) | Op::prefix(Rule::neg))
+    })
+}
+
+fn parse_expr(pairs: Pairs<Rule>) -> Expr {
+    pratt()
+        .map_primary(|primary| {
+            let span = primary.as_span();
+            let (start, end) = (span.start(), span.end());
+            let kind = match primary.as_rule() {
+                Rule::expr => return parse_expr(primary.into_inner()),
+                // The grammar only admits digits with an optional fraction
+                Rule::number => ExprKind::Number(primary.as_str().parse().unwrap_or_default()),
+                Rule::boolean => ExprKind::Boolean(primary.as_str() == "true"),
+                Rule::string => {
+                    let quoted = primary.as_str();
+                    ExprKind::Text(quoted[1..quoted.len() - 1].to_string())
+                }
+                Rule::name => ExprKind::Name(primary.as_str().to_string()),
+                Rule::call => {
+                    let mut inner = primary.into_inner();
+                    let function = inner.next().expect("a call starts with a name").as_str().to_string();
+                    let args = inner.map(|arg| parse_expr(arg.into_inner())).collect();
+                    ExprKind::Call { function, args }
+                }
+                rule => unreachable!("unexpected primary {:?}", rule),
+            };
+            Expr { kind, start, end }
+        })
+        .map_prefix(|op, operand| {
+            let op_kind = match op.as_rule() {
+                Rule::not => UnaryOp::Not,
+                _ => UnaryOp::Neg,
+            };
+            Expr {
+                start: op.as_span().start(),
+                end: operand.end,
+                kind: ExprKind::Unary {
+                    op: op_kind,
+                    operand: Box::new(operand),
+                },
+            }
+        })
+        .map_infix(|left, op, right| {
+            let op = match op.as_rule() {
+                Rule::or => BinaryOp::Or,
+                Rule::and => BinaryOp::And,
+                Rule::eq => BinaryOp::Eq,
+                Rule::ne => BinaryOp::Ne,
+                Rule::lt => BinaryOp::Lt,
+                Rule::le => BinaryOp::Le,
+                Rule::gt => BinaryOp::Gt,
+                Rule::ge => BinaryOp::Ge,
+                Rule::add => BinaryOp::Add,
+                Rule::sub => BinaryOp::Sub,
+                Rule::mul => BinaryOp::Mul,
+                _ => BinaryOp::Div,
+            };
+            Expr {
+                start: left.start,
+                end: right.end,
+                kind: ExprKind::Binary {
+                    op,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                },
+            }
+        })
+        .parse(pairs)
+}
+
+/// Evaluate a type-checked expression
+///
+/// Returns `None` if the expression does not type-check or divides by zero.
+pub fn eval(expr: &Expr, env: &dyn Env) -> Option<ExprValue> {
+    use ExprValue::*;
+
+    let value = match &expr.kind {
+        ExprKind::Number(number) => Number(*number),
+        ExprKind::Boolean(boolean) => Boolean(*boolean),
+        ExprKind::Text(text) => Text(text.clone()),
+        ExprKind::Name(name) => env.variable(name).unwrap_or_else(|| Symbol(Some(name.clone()))),
+        ExprKind::Event(event) => Symbol(Some(event.name.clone())),
+        ExprKind::Call { function, args } => match (function.as_str(), args.as_slice()) {
+            (
+                "happened",
+                [Expr {
+                    kind: ExprKind::Event(event),
+                    ..
+                }],
+            ) => Boolean(env.happened(event)),
+            _ => return None,
+        },
+        ExprKind::Unary { op, operand } => match (op, eval(operand, env)?) {
+            (UnaryOp::Not, Boolean(boolean)) => Boolean(!boolean),
+            (UnaryOp::Neg, Number(number)) => Number(-number),
+            _ => return None,
+        },
+        ExprKind::Binary { op, left, right } => {
+            let left = eval(left, env)?;
+            // `&&` and `||` only evaluate their right side when needed
+            match (op, &left) {
+                (BinaryOp::And, Boolean(false)) => return Some(Boolean(false)),
+                (BinaryOp::Or, Boolean(true)) => return Some(Boolean(true)),
+                _ => {}
+            }
+            let right = eval(right, env)?;
+            match (op, left, right) {
+                (BinaryOp::And | BinaryOp::Or, Boolean(_), Boolean(right)) => Boolean(right),
+                (BinaryOp::Eq, left, right) if left.ty() == right.ty() => Boolean(left == right),
+                (BinaryOp::Ne, left, right) if left.ty() == right.ty() => Boolean(left != right),
+                (BinaryOp::Lt, Number(a), Number(b)) => Boolean(a < b),
+                (BinaryOp::Le, Number(a), Number(b)) => Boolean(a <= b),
+                (BinaryOp::Gt, Number(a), Number(b)) => Boolean(a > b),
+                (BinaryOp::Ge, Number(a), Number(b)) => Boolean(a >= b),
+                (BinaryOp::Add, Number(a), Number(b)) => Number(a + b),
+                (BinaryOp::Add, Text(a), Text(b)) => Text(a + &b),
+                (BinaryOp::Sub, Number(a), Number(b)) => Number(a - b),
+                (BinaryOp::Mul, Number(a), Number(b)) => Number(a * b),
+                (BinaryOp::Div, Number(_), Number(0.0)) => return None,
+                (BinaryOp::Div, Number(a), Number(b)) => Number(a / b),
+                _ => return None,
+            }
+        }
+    };
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    /// `expr` fully parenthesized, to show how it was grouped
+    fn grouped(expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Number(number) => number.to_string(),
+            ExprKind::Boolean(boolean) => boolean.to_string(),
+            ExprKind::Text(text) => format!("'{}'", text),
+            ExprKind::Name(name) => name.clone(),
+            ExprKind::Event(event) => event.to_string(),
+            ExprKind::Call { function, args } => {
+                let args: Vec<String> = args.iter().map(grouped).collect();
+                format!("{}({})", function, args.join(", "))
+            }
+            ExprKind::Unary { op, operand } => {
+                let symbol = match op {
+                    UnaryOp::Not => "!",
+                    UnaryOp::Neg => "-",
+                };
+                format!("({}{})", symbol, grouped(operand))
+            }
+            ExprKind::Binary { op, left, right } => {
+                format!("({} {} {})", grouped(left), op.symbol(), grouped(right))
+            }
+        }
+    }
+
+    fn parsed(source: &str) -> String {
+        grouped(&parse(source).expect("test expression parses"))
+    }
+
+    #[test]
+    fn operators_bind_by_precedence() {
+        assert_eq!(parsed("1 + 2 * 3"), "(1 + (2 * 3))");
+        assert_eq!(parsed("(1 + 2) * 3"), "((1 + 2) * 3)");
+        assert_eq!(parsed("a < 1 + 2 == b"), "((a < (1 + 2)) == b)");
+        assert_eq!(parsed("a || b && c"), "(a || (b && c))");
+        assert_eq!(parsed("!a && b == c"), "((!a) && (b == c))");
+    }
+
+    #[test]
+    fn binary_operators_associate_to_the_left() {
+        assert_eq!(parsed("1 - 2 - 3"), "((1 - 2) - 3)");
+        assert_eq!(parsed("8 / 4 / 2"), "((8 / 4) / 2)");
+        assert_eq!(parsed("a || b || c"), "((a || b) || c)");
+    }
+
+    #[test]
+    fn unary_minus_binds_tighter_than_any_binary_operator() {
+        assert_eq!(parsed("-2 * 3"), "((-2) * 3)");
+        assert_eq!(parsed("1 - -2"), "(1 - (-2))");
+        assert_eq!(parsed("--x"), "(-(-x))");
+    }
+
+    #[test]
+    fn nodes_span_their_source() {
+        let expr = parse("tick + 1 > 2").expect("test expression parses");
+        assert_eq!((expr.start, expr.end), (0, 12));
+        let ExprKind::Binary { left, .. } = &expr.kind else {
+            panic!("expected a comparison, got {:?}", expr);
+        };
+        assert_eq!((left.start, left.end), (0, 8));
+    }
+
+    #[test]
+    fn syntax_errors_point_into_the_expression() {
+        for (source, at) in [("1 + * 2", 4), ("(a && b", 7), ("a b", 2)] {
+            let error = parse(source).expect_err("test expression is malformed");
+            assert!(matches!(error, CldError::Syntax { .. }), "{:?}", error);
+            assert_eq!(error.span().map(|span| span.start), Some(at), "{}", source);
+        }
+    }
+
+    struct TestEnv {
+        variables: BTreeMap<&'static str, ExprValue>,
+        fired: Vec<CitizenRef>,
+    }
+
+    impl Env for TestEnv {
+        fn variable(&self, name: &str) -> Option<ExprValue> {
+            self.variables.get(name).cloned()
+        }
+
+        fn happened(&self, event: &CitizenRef) -> bool {
+            self.fired.contains(event)
+        }
+    }
+
+    fn event(name: &str) -> CitizenRef {
+        CitizenRef {
+            kind: "Event".to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    /// Bind every `happened` argument to the `@Event` it names, as the
+    /// checker would
+    fn bind(expr: &mut Expr) {
+        match &mut expr.kind {
+            ExprKind::Call { function, args } if function == "happened" => {
+                for arg in args {
+                    if let ExprKind::Name(name) = &arg.kind {
+                        arg.kind = ExprKind::Event(event(name));
+                    }
+                }
+            }
+            ExprKind::Unary { operand, .. } => bind(operand),
+            ExprKind::Binary { left, right, .. } => {
+                bind(left);
+                bind(right);
+            }
+            _ => {}
+        }
+    }
+
+    fn evaluated(source: &str) -> Option<ExprValue> {
+        let env = TestEnv {
+            variables: BTreeMap::from([
+                ("stars", ExprValue::Number(10.0)),
+                ("mood", ExprValue::Symbol(Some("calm".to_string()))),
+            ]),
+            fired: vec![event("big_bang")],
+        };
+        let mut expr = parse(source).expect("test expression parses");
+        bind(&mut expr);
+        eval(&expr, &env)
+    }
+
+    #[test]
+    fn evaluates_arithmetic_comparisons_and_logic() {
+        assert_eq!(evaluated("stars * 2 - 1"), Some(ExprValue::Number(19.0)));
+        assert_eq!(evaluated("-stars / 4"), Some(ExprValue::Number(-2.5)));
+        assert_eq!(evaluated("'ab' + 'c'"), Some(ExprValue::Text("abc".to_string())));
+        assert_eq!(evaluated("stars >= 10 && mood == calm"), Some(ExprValue::Boolean(true)));
+        assert_eq!(evaluated("mood != calm || !happened(big_bang)"), Some(ExprValue::Boolean(false)));
+        assert_eq!(evaluated("happened(dawn)"), Some(ExprValue::Boolean(false)));
+        // Unbound, `happened` has no value
+        let unbound = parse("happened(big_bang)").expect("test expression parses");
+        let env = TestEnv {
+            variables: BTreeMap::new(),
+            fired: vec![event("big_bang")],
+        };
+        assert_eq!(eval(&unbound, &env), None);
+    }
+
+    #[test]
+    fn logic_short_circuits_and_ill_typed_expressions_have_no_value() {
+        // The right side would not evaluate, but is never reached
+        assert_eq!(evaluated("false && 1 + true"), Some(ExprValue::Boolean(false)));
+        assert_eq!(evaluated("true || 1 + true"), Some(ExprValue::Boolean(true)));
+        assert_eq!(evaluated("1 + true"), None);
+        assert_eq!(evaluated("stars == mood"), None);
+    }
+
+    #[test]
+    fn division_by_zero_has_no_value() {
+        assert_eq!(evaluated("stars / 0"), None);
+        assert_eq!(evaluated("stars / (stars - 10) > 1"), None);
+        assert_eq!(evaluated("0 / stars"), Some(ExprValue::Number(0.0)));
+    }
+}
@@ -6,15 +6,48 @@
 /// The main module for parsing CLD files
 pub mod parser;
 
+/// Source positions attached to parsed constructs
+pub mod span;
+
+/// Loading multi-file worlds through `@Import`
+pub mod loader;
+
+/// Diagnostics and rustc-style source snippets
+pub mod diagnostics;
+
+/// Structured error type with codes and severities
+pub mod error;
+
 /// The core citizen definitions
 pub mod citizens;
 
+/// The CLD v6 schema: field aliases, types and the schema checker
+pub mod schema;
+
+/// Typed views of citizens, produced by lowering the parsed fields
+pub mod typed;
+
+/// Reference resolution between citizens
+pub mod resolve;
+
 /// The world state management
 pub mod world;
 
 /// The timeline and event management
 pub mod timeline;
 
+/// Era intervals on timeline histories
+pub mod era;
+
+/// Event preconditions and effects expressions
+pub mod expr;
+
+/// Tick-based simulation of a world
+pub mod runtime;
+
+/// Emotion vocabulary and the emotional arcs of timelines
+pub mod emotion;
+
 /// The memory system for storing world history
 pub mod memory;
 
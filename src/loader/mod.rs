@@ -0,0 +1,308 @@
+//! Multi-file worlds
+//!
+//! `@Import "path.cld"` pulls the declarations of another file into the
+//! importing one. Paths are relative to the importing file and may contain
+//! the wildcards `*`, `?` and `**` (any number of directories). Each file is
+//! loaded once even if several files import it, and imported declarations
+//! come before those of the importing file, so a file can `merge` into
+//! citizens declared by its imports.
+
+use crate::error::CldError;
+use crate::parser::{CLDParser, Document, Import};
+use crate::span::SourceMap;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A world loaded from a root file and everything it imports
+#[derive(Debug, Default)]
+pub struct Loaded {
+    /// The declarations of every file, merged in load order
+    pub document: Document,
+    /// The loaded files; spans point into these by index
+    pub sources: SourceMap,
+    /// Syntax and import errors; the document is incomplete if any occurred
+    pub errors: Vec<CldError>,
+}
+
+/// Load `path` and, recursively, every file it imports
+///
+/// Fails only if the root file itself cannot be read; problems with
+/// imported files are reported in [`Loaded::errors`].
+pub fn load(path: impl AsRef<Path>) -> io::Result<Loaded> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path)?;
+    let mut loader = Loader::default();
+    loader.add(path, path.display().to_string(), source);
+    Ok(loader.loaded)
+}
+
+#[derive(Default)]
+struct Loader {
+    loaded: Loaded,
+    /// Canonical paths of every file added so far
+    seen: HashSet<PathBuf>,
+    /// Files being loaded, outermost first, with their display names
+    stack: Vec<(PathBuf, String)>,
+}
+
+impl Loader {
+    /// Parse a file, load its imports and then merge its declarations
+    fn add(&mut self, path: &Path, name: String, source: String) {
+        let key = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let file = self.loaded.sources.len();
+        let parsed = CLDParser::parse_document(&source);
+        self.loaded.sources.add(name.clone(), source);
+        self.seen.insert(key.clone());
+
+        let mut document = match parsed {
+            Ok(document) => document,
+            Err(e) => {
+                self.loaded.errors.push(e.in_file(file));
+                return;
+            }
+        };
+        document.set_file(file);
+
+        let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        self.stack.push((key, name));
+        for import in &document.imports {
+            self.import(&dir, import);
+        }
+        self.stack.pop();
+
+        let merged = &mut self.loaded.document;
+        merged.imports.extend(document.imports);
+        merged.citizens.extend(document.citizens);
+        merged.schemas.extend(document.schemas);
+    }
+
+    /// Load every file matched by one `@Import` of a file in `dir`
+    fn import(&mut self, dir: &Path, import: &Import) {
+        let is_glob = import.path.contains(['*', '?']);
+        let paths = if is_glob {
+            let matches = glob(dir, &import.path);
+            if matches.is_empty() {
+                self.fail(import, "no files match this pattern".to_string());
+            }
+            matches
+        } else {
+            vec![dir.join(&import.path)]
+        };
+
+        for path in paths {
+            let key = match fs::canonicalize(&path) {
+                Ok(key) => key,
+                Err(e) => {
+                    self.fail(import, e.to_string());
+                    continue;
+                }
+            };
+            if let Some(position) = self.stack.iter().position(|(open, _)| *open == key) {
+                // A glob never matches the file that contains it
+                if is_glob && position == self.stack.len() - 1 {
+                    continue;
+                }
+                let mut chain: Vec<String> = self.stack[position..].iter().map(|(_, name)| name.clone()).collect();
+                chain.push(self.stack[position].1.clone());
+                self.loaded.errors.push(CldError::ImportCycle {
+                    path: import.path.clone(),
+                    chain,
+                    span: import.span,
+                });
+                continue;
+            }
+            if self.seen.contains(&key) {
+                continue;
+            }
+            match fs::read_to_string(&path) {
+                Ok(source) => self.add(&path, path.display().to_string(), source),
+                Err(e) => self.fail(import, e.to_string()),
+            }
+        }
+    }
+
+    fn fail(&mut self, import: &Import, message: String) {
+        self.loaded.errors.push(CldError::ImportFailed {
+            path: import.path.clone(),
+            message,
+            span: import.span,
+        });
+    }
+}
+
+/// Files matching `pattern`, relative to `dir` unless absolute, in sorted order
+fn glob(dir: &Path, pattern: &str) -> Vec<PathBuf> {
+    let base = if Path::new(pattern).is_absolute() {
+        PathBuf::from("/")
+    } else {
+        dir.to_path_buf()
+    };
+    let components: Vec<&str> = pattern.split('/').filter(|c| !c.is_empty()).collect();
+    let mut matches = Vec::new();
+    expand(&base, &components, &mut matches);
+    matches.sort();
+    matches.dedup();
+    matches
+}
+
+fn expand(base: &Path, components: &[&str], matches: &mut Vec<PathBuf>) {
+    let Some((first, rest)) = components.split_first() else {
+        if base.is_file() {
+            matches.push(base.to_path_buf());
+        }
+        return;
+    };
+
+    if *first == "**" {
+        // Zero directories, then one more level with `**` still pending
+        expand(base, rest, matches);
+        for entry in sorted_entries(base) {
+            if entry.is_dir() {
+                expand(&entry, components, matches);
+            }
+        }
+    } else if first.contains(['*', '?']) {
+        for entry in sorted_entries(base) {
+            let name = entry.file_name().and_then(|name| name.to_str()).unwrap_or("");
+            if wildcard_match(first, name) {
+                expand(&entry, rest, matches);
+            }
+        }
+    } else {
+        expand(&base.join(first), rest, matches);
+    }
+}
+
+fn sorted_entries(dir: &Path) -> Vec<PathBuf> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect())
+        .unwrap_or_default();
+    entries.sort();
+    entries
+}
+
+/// Match a file name against a pattern where `*` is any run of characters
+/// and `?` is a single character
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Position of the last `*` and the name position it was tried at
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, tried)) => {
+                    p = star + 1;
+                    n = tried + 1;
+                    backtrack = Some((star, tried + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Citizen;
+
+    /// A fresh directory holding `files`, as (relative path, source)
+    fn tree(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("cld-os-loader-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&root);
+        for (path, source) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().expect("test files live in a directory")).expect("create test directory");
+            fs::write(&path, source).expect("write test file");
+        }
+        root
+    }
+
+    fn event_names(loaded: &Loaded) -> Vec<&str> {
+        loaded
+            .document
+            .citizens
+            .iter()
+            .filter_map(|citizen| match citizen {
+                Citizen::Event(event) => Some(event.name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn wildcards_match_runs_and_single_characters() {
+        assert!(wildcard_match("*.cld", "world.cld"));
+        assert!(wildcard_match("*.cld", ".cld"));
+        assert!(!wildcard_match("*.cld", "world.cld.bak"));
+        assert!(wildcard_match("w?rld.cld", "world.cld"));
+        assert!(!wildcard_match("w?rld.cld", "wrld.cld"));
+        assert!(wildcard_match("a*b*c", "aXbYbZc"));
+        assert!(!wildcard_match("a*b*c", "aXbYbZ"));
+        assert!(wildcard_match("**", ""));
+        assert!(wildcard_match("星*", "星图.cld"));
+        assert!(!wildcard_match("", "x"));
+    }
+
+    #[test]
+    fn reports_import_cycles_with_their_chain() {
+        let root = tree(
+            "cycle",
+            &[
+                ("a.cld", "@Import \"b.cld\"\n@Event[a] { }"),
+                ("b.cld", "@Import \"a.cld\"\n@Event[b] { }"),
+            ],
+        );
+        let a = root.join("a.cld");
+        let loaded = load(&a).expect("root file loads");
+        let [CldError::ImportCycle { path, chain, .. }] = &loaded.errors[..] else {
+            panic!("expected one import cycle, got {:?}", loaded.errors);
+        };
+        assert_eq!(path, "a.cld");
+        let a = a.display().to_string();
+        assert_eq!(chain, &[a.clone(), root.join("b.cld").display().to_string(), a]);
+        // Each file's declarations are still loaded once
+        assert_eq!(event_names(&loaded), ["b", "a"]);
+        fs::remove_dir_all(root).ok();
+    }
+
+    #[test]
+    fn a_file_importing_itself_is_a_cycle() {
+        let root = tree("self", &[("a.cld", "@Import \"a.cld\"\n@Event[a] { }")]);
+        let loaded = load(root.join("a.cld")).expect("root file loads");
+        assert!(matches!(&loaded.errors[..], [CldError::ImportCycle { .. }]), "{:?}", loaded.errors);
+        fs::remove_dir_all(root).ok();
+    }
+
+    #[test]
+    fn globs_skip_their_own_file_and_load_shared_imports_once() {
+        let root = tree(
+            "glob",
+            &[
+                ("main.cld", "@Import \"*.cld\"\n@Import \"regions/**/*.cld\"\n@Event[main] { }"),
+                ("base.cld", "@Event[base] { }"),
+                ("regions/north.cld", "@Import \"../base.cld\"\n@Event[north] { }"),
+                ("regions/deep/south.cld", "@Import \"../../base.cld\"\n@Event[south] { }"),
+            ],
+        );
+        let loaded = load(root.join("main.cld")).expect("root file loads");
+        assert!(loaded.errors.is_empty(), "{:?}", loaded.errors);
+        assert_eq!(event_names(&loaded), ["base", "south", "north", "main"]);
+        fs::remove_dir_all(root).ok();
+    }
+}
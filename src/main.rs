@@ -1,154 +1,497 @@
 //! Main entry point for the cld-os CLI
 
 use std::env;
-use std::fs;
-use cld_os::parser::CLDParser;
-use cld_os::world::{World, validate_world};
+use cld_os::citizens::Value;
+use cld_os::diagnostics::{Report, Severity};
+use cld_os::emotion::{arcs, ArcSettings, Vocabulary};
+use cld_os::error::CldError;
+use cld_os::loader::{self, Loaded};
+use cld_os::parser::AstDocument;
+use cld_os::runtime::{Outcome, Simulation};
+use cld_os::typed::lower_world;
+use cld_os::span::SourceMap;
+use cld_os::world::{World, sort_by_position, validate_world};
+
+/// Output format for diagnostics
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Text,
+    Json,
+}
+
+/// Options accepted by `run`
+struct RunOptions {
+    ticks: u64,
+    /// Timeline to run instead of the first one with a history
+    timeline: Option<String>,
+    /// Seed to use instead of `Origin.seed`
+    seed: Option<u64>,
+    /// Ticks an event may wait for its `requires` before it is skipped
+    patience: Option<u64>,
+}
+
+/// Options accepted by `arc`
+struct ArcOptions {
+    /// Most ticks to run each timeline for
+    ticks: u64,
+    /// Timeline to trace instead of every one with a history
+    timeline: Option<String>,
+    settings: ArcSettings,
+}
+
+/// Options accepted by `validate`
+struct ValidateOptions {
+    format: Format,
+    /// Error codes or rules to suppress, e.g. `E0201` or `!Era`
+    allow: Vec<String>,
+}
+
+fn print_usage(program: &str) {
+    eprintln!("Usage: {} <command> [args...]", program);
+    eprintln!("Commands:");
+    eprintln!("  parse <cld-file>     Parse a CLD file and its imports and output JSON AST");
+    eprintln!("  validate <cld-file>  Validate a CLD file against CLD v6 rules");
+    eprintln!("      --format <text|json>  Diagnostic output format (default: text)");
+    eprintln!("      --allow <code|rule>   Suppress diagnostics with this code or rule (repeatable)");
+    eprintln!("  run <cld-file>       Validate a CLD file, then simulate it and print its history");
+    eprintln!("      --ticks <n>           Number of ticks to run (default: 10)");
+    eprintln!("      --timeline <name>     Timeline to run (default: the first with events)");
+    eprintln!("      --seed <n>            Seed for random numbers (default: Origin.seed, or 0)");
+    eprintln!("      --patience <n>        Ticks an event may wait for its requires (default: 10)");
+    eprintln!("  arc <cld-file>       Validate a CLD file, then chart the emotional arc of its timelines");
+    eprintln!("      --timeline <name>     Timeline to chart (default: every one with events)");
+    eprintln!("      --ticks <n>           Most ticks to run each timeline for (default: 100)");
+    eprintln!("      --max-peaks <n>       Most peaks allowed in a row (default: 3)");
+    eprintln!("      --min-flat <n>        Fewest beats that make a flat stretch (default: 4)");
+}
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    
+
     if args.len() < 2 {
-        eprintln!("Usage: {} <command> [args...]", args[0]);
-        eprintln!("Commands:");
-        eprintln!("  parse <cld-file>     Parse a CLD file and output JSON AST");
-        eprintln!("  validate <cld-file>  Validate a CLD file against CLD v6 rules");
+        print_usage(&args[0]);
         std::process::exit(1);
     }
-    
+
     let command = &args[1];
-    
+
     match command.as_str() {
         "parse" => {
             if args.len() < 3 {
                 eprintln!("Usage: {} parse <cld-file>", args[0]);
                 std::process::exit(1);
             }
-            
+
             let file_path = &args[2];
             parse_cld_file(file_path);
         }
         "validate" => {
             if args.len() < 3 {
-                eprintln!("Usage: {} validate <cld-file>", args[0]);
+                eprintln!("Usage: {} validate <cld-file> [--format <text|json>] [--allow <code|rule>]...", args[0]);
+                std::process::exit(1);
+            }
+
+            let file_path = &args[2];
+            match parse_validate_options(&args[3..]) {
+                Ok(options) => validate_cld_file(file_path, &options),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    print_usage(&args[0]);
+                    std::process::exit(1);
+                }
+            }
+        }
+        "run" => {
+            if args.len() < 3 {
+                eprintln!("Usage: {} run <cld-file> [--ticks <n>] [--timeline <name>] [--seed <n>] [--patience <n>]", args[0]);
+                std::process::exit(1);
+            }
+
+            let file_path = &args[2];
+            match parse_run_options(&args[3..]) {
+                Ok(options) => run_cld_file(file_path, &options),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    print_usage(&args[0]);
+                    std::process::exit(1);
+                }
+            }
+        }
+        "arc" => {
+            if args.len() < 3 {
+                eprintln!(
+                    "Usage: {} arc <cld-file> [--timeline <name>] [--ticks <n>] [--max-peaks <n>] [--min-flat <n>]",
+                    args[0]
+                );
                 std::process::exit(1);
             }
-            
+
             let file_path = &args[2];
-            validate_cld_file(file_path);
+            match parse_arc_options(&args[3..]) {
+                Ok(options) => arc_cld_file(file_path, &options),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    print_usage(&args[0]);
+                    std::process::exit(1);
+                }
+            }
         }
         _ => {
             eprintln!("Unknown command: {}", command);
-            eprintln!("Usage: {} <command> [args...]", args[0]);
-            eprintln!("Commands:");
-            eprintln!("  parse <cld-file>     Parse a CLD file and output JSON AST");
-            eprintln!("  validate <cld-file>  Validate a CLD file against CLD v6 rules");
+            print_usage(&args[0]);
             std::process::exit(1);
         }
     }
 }
 
+fn parse_validate_options(args: &[String]) -> Result<ValidateOptions, String> {
+    let mut options = ValidateOptions {
+        format: Format::Text,
+        allow: Vec::new(),
+    };
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => match args.next().map(String::as_str) {
+                Some("text") => options.format = Format::Text,
+                Some("json") => options.format = Format::Json,
+                Some(other) => return Err(format!("Unknown format: {}", other)),
+                None => return Err("--format needs a value".to_string()),
+            },
+            "--allow" => match args.next() {
+                Some(code) => options.allow.push(code.clone()),
+                None => return Err("--allow needs an error code or rule name".to_string()),
+            },
+            other => return Err(format!("Unknown option: {}", other)),
+        }
+    }
+
+    Ok(options)
+}
+
+fn parse_run_options(args: &[String]) -> Result<RunOptions, String> {
+    let mut options = RunOptions {
+        ticks: 10,
+        timeline: None,
+        seed: None,
+        patience: None,
+    };
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ticks" => match args.next().map(|ticks| ticks.parse()) {
+                Some(Ok(ticks)) => options.ticks = ticks,
+                Some(Err(_)) => return Err("--ticks needs a non-negative whole number".to_string()),
+                None => return Err("--ticks needs a value".to_string()),
+            },
+            "--seed" => match args.next().map(|seed| seed.parse()) {
+                Some(Ok(seed)) => options.seed = Some(seed),
+                Some(Err(_)) => return Err("--seed needs a non-negative whole number".to_string()),
+                None => return Err("--seed needs a value".to_string()),
+            },
+            "--patience" => match args.next().map(|patience| patience.parse()) {
+                Some(Ok(patience)) => options.patience = Some(patience),
+                Some(Err(_)) => return Err("--patience needs a non-negative whole number".to_string()),
+                None => return Err("--patience needs a value".to_string()),
+            },
+            "--timeline" => match args.next() {
+                Some(timeline) => options.timeline = Some(timeline.clone()),
+                None => return Err("--timeline needs a timeline name".to_string()),
+            },
+            other => return Err(format!("Unknown option: {}", other)),
+        }
+    }
+
+    Ok(options)
+}
+
+fn parse_arc_options(args: &[String]) -> Result<ArcOptions, String> {
+    let mut options = ArcOptions {
+        ticks: 100,
+        timeline: None,
+        settings: ArcSettings::default(),
+    };
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ticks" => match args.next().map(|ticks| ticks.parse()) {
+                Some(Ok(ticks)) => options.ticks = ticks,
+                Some(Err(_)) => return Err("--ticks needs a non-negative whole number".to_string()),
+                None => return Err("--ticks needs a value".to_string()),
+            },
+            "--timeline" => match args.next() {
+                Some(timeline) => options.timeline = Some(timeline.clone()),
+                None => return Err("--timeline needs a timeline name".to_string()),
+            },
+            "--max-peaks" => match args.next().map(|max| max.parse()) {
+                Some(Ok(max)) => options.settings.max_peaks = max,
+                Some(Err(_)) => return Err("--max-peaks needs a non-negative whole number".to_string()),
+                None => return Err("--max-peaks needs a value".to_string()),
+            },
+            "--min-flat" => match args.next().map(|min| min.parse()) {
+                Some(Ok(min)) => options.settings.min_flat = min,
+                Some(Err(_)) => return Err("--min-flat needs a non-negative whole number".to_string()),
+                None => return Err("--min-flat needs a value".to_string()),
+            },
+            other => return Err(format!("Unknown option: {}", other)),
+        }
+    }
+
+    Ok(options)
+}
+
 fn parse_cld_file(file_path: &str) {
-    match fs::read_to_string(file_path) {
-        Ok(content) => {
-            match CLDParser::parse_cld(&content) {
-                Ok(citizens) => {
-                    // Print citizen count for each type
-                    let mut origin_count = 0;
-                    let mut timeline_count = 0;
-                    let mut event_count = 0;
-                    let mut core_event_count = 0;
-                    let mut niche_count = 0;
-                    let mut era_count = 0;
-                    let mut generator_count = 0;
-                    let mut memory_count = 0;
-                    let mut immune_count = 0;
-                    
-                    for citizen in &citizens {
-                        match citizen {
-                            cld_os::parser::Citizen::Origin(_) => origin_count += 1,
-                            cld_os::parser::Citizen::Timeline(_) => timeline_count += 1,
-                            cld_os::parser::Citizen::Event(_) => event_count += 1,
-                            cld_os::parser::Citizen::CoreEvent(_) => core_event_count += 1,
-                            cld_os::parser::Citizen::Niche(_) => niche_count += 1,
-                            cld_os::parser::Citizen::Era(_) => era_count += 1,
-                            cld_os::parser::Citizen::Generator(_) => generator_count += 1,
-                            cld_os::parser::Citizen::Memory(_) => memory_count += 1,
-                            cld_os::parser::Citizen::Immune(_) => immune_count += 1,
-                        }
-                    }
-                    
-                    println!("Parsed CLD file: {}", file_path);
-                    println!("Citizens parsed:");
-                    println!("  Origin: {}", origin_count);
-                    println!("  Timeline: {}", timeline_count);
-                    println!("  Event: {}", event_count);
-                    println!("  CoreEvent: {}", core_event_count);
-                    println!("  Niche: {}", niche_count);
-                    println!("  Era: {}", era_count);
-                    println!("  Generator: {}", generator_count);
-                    println!("  Memory: {}", memory_count);
-                    println!("  Immune: {}", immune_count);
-                    
-                    // Try to build world and validate
-                    match World::from_citizens(citizens) {
-                        Ok(world) => {
-                            println!("\nWorld built successfully!");
-                            if let Some(origin) = &world.origin {
-                                println!("Origin: {} with {} fields", origin.name, origin.fields.len());
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("Error building world: {}", e);
-                            std::process::exit(1);
-                        }
-                    }
-                }
-                Err(e) => {
-                    eprintln!("Error parsing CLD content: {}", e);
-                    std::process::exit(1);
-                }
-            }
+    let Loaded { document, sources, errors } = load_or_exit(file_path);
+    if !errors.is_empty() {
+        for e in &errors {
+            eprint!("{}", e.to_diagnostic().render_in(&sources));
         }
+        std::process::exit(1);
+    }
+
+    let mut document = AstDocument::new(file_path, document);
+    if sources.len() > 1 {
+        document.files = sources.names();
+    }
+    match serde_json::to_string_pretty(&document) {
+        Ok(json) => println!("{}", json),
         Err(e) => {
-            eprintln!("Error reading file {}: {}", file_path, e);
+            eprintln!("Error serializing AST: {}", e);
             std::process::exit(1);
         }
     }
 }
 
-fn validate_cld_file(file_path: &str) {
-    match fs::read_to_string(file_path) {
-        Ok(content) => {
-            match CLDParser::parse_cld(&content) {
-                Ok(citizens) => {
-                    match World::from_citizens(citizens) {
-                        Ok(world) => {
-                            match validate_world(&world) {
-                                Ok(()) => {
-                                    println!("Validation successful: {} is a valid CLD v6 file", file_path);
-                                }
-                                Err(e) => {
-                                    eprintln!("Validation failed: {}", e);
-                                    std::process::exit(1);
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("Error building world: {}", e);
-                            std::process::exit(1);
-                        }
-                    }
-                }
-                Err(e) => {
-                    eprintln!("Error parsing CLD content: {}", e);
-                    std::process::exit(1);
-                }
+fn validate_cld_file(file_path: &str, options: &ValidateOptions) {
+    let Loaded { document, sources, mut errors } = load_or_exit(file_path);
+    // A file that failed to parse or import leaves the world incomplete, so
+    // validating it would only add noise
+    if errors.is_empty() {
+        // Keep validating a partially built world so that every problem is
+        // reported in one run
+        let (world, build_errors) = World::build_document(document);
+        errors.extend(build_errors);
+        errors.extend(validate_world(&world));
+    }
+    sort_by_position(&mut errors);
+    report(file_path, &sources, errors, options);
+}
+
+fn run_cld_file(file_path: &str, options: &RunOptions) {
+    let world = load_valid_world(file_path, "running");
+
+    let mut simulation = match &options.timeline {
+        Some(name) => match Simulation::on_timeline(&world, name) {
+            Some(simulation) => simulation,
+            None => {
+                eprintln!("Unknown timeline: {}", name);
+                std::process::exit(1);
             }
+        },
+        None => Simulation::new(&world),
+    };
+    if let Some(seed) = options.seed {
+        simulation = simulation.with_seed(seed);
+    }
+    if let Some(patience) = options.patience {
+        simulation = simulation.with_patience(patience);
+    }
+    simulation.run(options.ticks);
+
+    for record in simulation.history() {
+        let era = record.era.as_deref().unwrap_or("-");
+        let event = record.event.as_ref().map_or("-", |event| event.name.as_str());
+        let outcome = match &record.outcome {
+            Outcome::Fired => "fired".to_string(),
+            Outcome::Idle => "idle".to_string(),
+            Outcome::Waiting => "waiting: requires does not hold".to_string(),
+            Outcome::Skipped => "skipped: requires still does not hold".to_string(),
+            Outcome::Missed => "missed: its chance did not come up".to_string(),
+            Outcome::OutOfBounds => "refused: entropy would leave its bounds".to_string(),
+            Outcome::Refused(breaches) => {
+                let reasons: Vec<String> = breaches.iter().map(ToString::to_string).collect();
+                format!("refused: {}", reasons.join("; "))
+            }
+        };
+        println!(
+            "tick {:>4}  entropy {:.3}  era {:<16} {:<24} {}",
+            record.tick, record.entropy, era, event, outcome
+        );
+    }
+
+    let state = simulation.state();
+    let on = match simulation.timeline() {
+        Some(timeline) => format!(" on timeline '{}'", timeline.name),
+        None => String::new(),
+    };
+    let skipped = simulation
+        .history()
+        .iter()
+        .filter(|record| record.outcome == Outcome::Skipped)
+        .count();
+    let skipped = match skipped {
+        0 => String::new(),
+        skipped => format!(", {} skipped", skipped),
+    };
+    println!(
+        "Ran {} ticks{} with seed {}: {} events fired{}, final entropy {:.3}",
+        state.tick,
+        on,
+        simulation.seed(),
+        state.fired.len(),
+        skipped,
+        state.entropy
+    );
+    for (key, value) in &state.variables {
+        println!("  {} = {}", key, display_value(value));
+    }
+}
+
+fn arc_cld_file(file_path: &str, options: &ArcOptions) {
+    let world = load_valid_world(file_path, "charting");
+    let (typed, _) = lower_world(&world);
+    let vocabulary = Vocabulary::from_origin(typed.origin.as_ref());
+
+    let mut arcs = arcs(&world, &vocabulary, options.ticks);
+    if let Some(name) = &options.timeline {
+        arcs.retain(|arc| arc.timeline.as_ref() == Some(name));
+        if arcs.is_empty() {
+            eprintln!("Unknown timeline, or one without events: {}", name);
+            std::process::exit(1);
+        }
+    }
+
+    for arc in &arcs {
+        match &arc.timeline {
+            Some(timeline) => println!("Timeline '{}'", timeline),
+            None => println!("Events in causal order"),
         }
+        for beat in &arc.beats {
+            println!(
+                "tick {:>4}  {:<24} {:<12} {:>+5.2} {:>4}  {}",
+                beat.tick,
+                beat.event,
+                beat.emotion.as_deref().unwrap_or("-"),
+                beat.valence,
+                beat.intensity.round(),
+                "#".repeat((beat.intensity / 5.0).round() as usize)
+            );
+        }
+        let findings = arc.analyze(&options.settings);
+        println!(
+            "{} beats, climax {}, {} pacing problem{}",
+            arc.beats.len(),
+            arc.climax().round(),
+            findings.len(),
+            if findings.len() == 1 { "" } else { "s" }
+        );
+        for finding in &findings {
+            println!("  {}", finding);
+        }
+    }
+}
+
+/// Load, build and validate `file_path`, exiting unless the world is valid
+///
+/// Diagnostics are printed either way; warnings do not stop `doing`.
+fn load_valid_world(file_path: &str, doing: &str) -> World {
+    let Loaded { document, sources, mut errors } = load_or_exit(file_path);
+    let mut world = None;
+    if errors.is_empty() {
+        let (built, build_errors) = World::build_document(document);
+        errors.extend(build_errors);
+        errors.extend(validate_world(&built));
+        world = Some(built);
+    }
+    sort_by_position(&mut errors);
+
+    for e in &errors {
+        eprintln!("{}", e.to_diagnostic().render_in(&sources));
+    }
+    match world {
+        Some(world) if !errors.iter().any(|e| e.severity() == Severity::Error) => world,
+        _ => {
+            eprintln!("Not {} {}: the world is not valid", doing, file_path);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// A short rendering of an effect value for `run` output
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::String(text) => format!("{:?}", text),
+        Value::Number(number) => number.to_string(),
+        Value::Boolean(boolean) => boolean.to_string(),
+        Value::Identifier(identifier) => identifier.clone(),
+        Value::List(items) => {
+            let items: Vec<String> = items.iter().map(|item| display_value(&item.node)).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Map(fields) => {
+            let entries: Vec<String> = fields
+                .iter()
+                .map(|field| format!("{}: {}", field.key, display_value(&field.value.node)))
+                .collect();
+            format!("{{ {} }}", entries.join(", "))
+        }
+    }
+}
+
+/// Load `file_path` and its imports, exiting if the file cannot be read
+fn load_or_exit(file_path: &str) -> Loaded {
+    match loader::load(file_path) {
+        Ok(loaded) => loaded,
         Err(e) => {
             eprintln!("Error reading file {}: {}", file_path, e);
             std::process::exit(1);
         }
     }
-}
\ No newline at end of file
+}
+
+/// Print the diagnostics for `file_path` and exit non-zero if any remain
+fn report(file_path: &str, sources: &SourceMap, errors: Vec<CldError>, options: &ValidateOptions) {
+    let diagnostics: Vec<_> = errors
+        .iter()
+        .map(CldError::to_diagnostic)
+        .filter(|diagnostic| !options.allow.iter().any(|filter| diagnostic.matches(filter)))
+        .collect();
+    let mut report = Report::new(file_path, diagnostics);
+    if sources.len() > 1 {
+        report.files = sources.names();
+    }
+
+    match options.format {
+        Format::Json => match serde_json::to_string_pretty(&report) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("Error serializing diagnostics: {}", e);
+                std::process::exit(1);
+            }
+        },
+        Format::Text => {
+            for diagnostic in &report.diagnostics {
+                eprintln!("{}", diagnostic.render_in(sources));
+            }
+            if report.errors == 0 {
+                println!("Validation successful: {} is a valid CLD v6 file", file_path);
+            } else {
+                eprintln!(
+                    "Validation failed: {} ({} error{}, {} warning{})",
+                    file_path,
+                    report.errors,
+                    if report.errors == 1 { "" } else { "s" },
+                    report.warnings,
+                    if report.warnings == 1 { "" } else { "s" }
+                );
+            }
+        }
+    }
+
+    if report.errors > 0 {
+        std::process::exit(1);
+    }
+}
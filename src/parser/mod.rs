@@ -4,63 +4,124 @@
 
 use pest_derive::Parser;
 
+mod strings;
+
 #[derive(Parser)]
 #[grammar = "parser/grammar/cld.pest"]
 pub struct CLDParser;
 
 use crate::citizens::*;
+use crate::error::CldError;
+use crate::schema::{canonical_field_name, FieldSchema, FieldType, SchemaDecl};
+use crate::span::{Span, Spanned};
 use pest::Parser as PestParser;
+use serde::{Deserialize, Serialize};
 
 impl CLDParser {
     /// Parse a CLD file content into citizen objects
-    pub fn parse_cld(content: &str) -> Result<Vec<Citizen>, Box<dyn std::error::Error>> {
-        let pairs = CLDParser::parse(Rule::cld_file, content)?;
+    pub fn parse_cld(content: &str) -> Result<Vec<Citizen>, CldError> {
+        Ok(CLDParser::parse_document(content)?.citizens)
+    }
+
+    /// Parse a CLD file content into citizens and the other declarations
+    /// that accompany them, such as `@Schema`
+    pub fn parse_document(content: &str) -> Result<Document, CldError> {
+        let pairs = CLDParser::parse(Rule::cld_file, content).map_err(CldError::from_pest)?;
+        let mut imports = Vec::new();
         let mut citizens = Vec::new();
+        let mut schemas = Vec::new();
 
         for pair in pairs {
             // Handle the top-level cld_file rule
             if pair.as_rule() == Rule::cld_file {
                 // Iterate through the children of cld_file
+                // Namespace that citizen names are declared in, for the rest
+                // of the file
+                let mut namespace = String::new();
                 for inner_pair in pair.into_inner() {
-                    match inner_pair.as_rule() {
-                        Rule::origin => {
-                            citizens.push(Citizen::Origin(parse_origin(inner_pair)?));
-                        }
-                        Rule::timeline => {
-                            citizens.push(Citizen::Timeline(parse_timeline(inner_pair)?));
-                        }
-                        Rule::event => {
-                            citizens.push(Citizen::Event(parse_event(inner_pair)?));
+                    let citizen = match inner_pair.as_rule() {
+                        Rule::import => {
+                            imports.push(parse_import(inner_pair)?);
+                            None
                         }
-                        Rule::core_event => {
-                            citizens.push(Citizen::CoreEvent(parse_core_event(inner_pair)?));
+                        Rule::namespace => {
+                            namespace = inner_pair.into_inner().as_str().to_string();
+                            None
                         }
-                        Rule::niche => {
-                            citizens.push(Citizen::Niche(parse_niche(inner_pair)?));
+                        Rule::origin => Some(Citizen::Origin(parse_origin(inner_pair)?)),
+                        Rule::timeline => Some(Citizen::Timeline(parse_timeline(inner_pair)?)),
+                        Rule::event => Some(Citizen::Event(parse_event(inner_pair)?)),
+                        Rule::core_event => Some(Citizen::CoreEvent(parse_core_event(inner_pair)?)),
+                        Rule::niche => Some(Citizen::Niche(parse_niche(inner_pair)?)),
+                        Rule::era => Some(Citizen::Era(parse_era(inner_pair)?)),
+                        Rule::generator => Some(Citizen::Generator(parse_generator(inner_pair)?)),
+                        Rule::memory => Some(Citizen::Memory(parse_memory(inner_pair)?)),
+                        Rule::immune => Some(Citizen::Immune(parse_immune(inner_pair)?)),
+                        Rule::schema => {
+                            schemas.push(parse_schema(inner_pair)?);
+                            None
                         }
-                        Rule::era => {
-                            citizens.push(Citizen::Era(parse_era(inner_pair)?));
-                        }
-                        Rule::generator => {
-                            citizens.push(Citizen::Generator(parse_generator(inner_pair)?));
-                        }
-                        Rule::memory => {
-                            citizens.push(Citizen::Memory(parse_memory(inner_pair)?));
-                        }
-                        Rule::immune => {
-                            citizens.push(Citizen::Immune(parse_immune(inner_pair)?));
-                        }
-                        _ => (),
+                        _ => None,
+                    };
+                    if let Some(mut citizen) = citizen {
+                        citizen.set_namespace(&namespace);
+                        citizens.push(citizen);
                     }
                 }
             }
         }
 
-        Ok(citizens)
+        Ok(Document { imports, citizens, schemas })
     }
 }
 
-#[derive(Debug)]
+/// An `@Import "path"` directive
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Import {
+    /// The path or glob as written, relative to the importing file
+    pub path: String,
+    pub span: Span,
+}
+
+/// Everything declared in one CLD file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Document {
+    /// `@Import` directives in declaration order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub imports: Vec<Import>,
+    /// Citizens in declaration order
+    pub citizens: Vec<Citizen>,
+    /// `@Schema` declarations in declaration order
+    pub schemas: Vec<SchemaDecl>,
+}
+
+impl Document {
+    /// Point every span of the document into file `file` of a source map
+    pub fn set_file(&mut self, file: usize) {
+        for import in &mut self.imports {
+            import.span.file = file;
+        }
+        for citizen in &mut self.citizens {
+            citizen.set_file(file);
+        }
+        for decl in &mut self.schemas {
+            decl.kind_span.file = file;
+            decl.span.file = file;
+            for field in &mut decl.fields {
+                if let Some(span) = &mut field.span {
+                    span.file = file;
+                }
+            }
+        }
+    }
+}
+
+/// A parsed top-level declaration
+///
+/// Serialized with an inline `"kind"` tag next to the citizen's own fields,
+/// e.g. `{"kind": "Event", "name": "...", "fields": {...}, "span": {...}}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind")]
 pub enum Citizen {
     Origin(Origin),
     Timeline(Timeline),
@@ -73,24 +134,90 @@ pub enum Citizen {
     Immune(Immune),
 }
 
-fn parse_value(pair: pest::iterators::Pair<Rule>) -> Result<Value, Box<dyn std::error::Error>> {
+impl Citizen {
+    /// Qualify the citizen's name with the namespace it is declared in
+    fn set_namespace(&mut self, namespace: &str) {
+        macro_rules! qualify {
+            ($($kind:ident),*) => {
+                match self {
+                    $(Citizen::$kind(c) => c.name = qualify(namespace, &c.name),)*
+                }
+            };
+        }
+        qualify!(Origin, Timeline, Event, CoreEvent, Niche, Era, Generator, Memory, Immune);
+    }
+
+    /// Point every span of the citizen into file `file` of a source map
+    pub fn set_file(&mut self, file: usize) {
+        macro_rules! relocate {
+            ($($kind:ident),*) => {
+                match self {
+                    $(Citizen::$kind(c) => {
+                        c.name_span.file = file;
+                        c.span.file = file;
+                        c.fields.set_file(file);
+                    })*
+                }
+            };
+        }
+        relocate!(Origin, Timeline, Event, CoreEvent, Niche, Era, Generator, Memory, Immune);
+    }
+}
+
+/// Version of the JSON AST layout produced by [`AstDocument`]
+///
+/// Bump this whenever the serialized shape of citizens or values changes.
+pub const AST_FORMAT_VERSION: u32 = 7;
+
+/// The JSON document printed by `cld-os parse`
+///
+/// Citizens and schemas appear in source declaration order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AstDocument {
+    pub version: u32,
+    pub source: String,
+    /// Every file the document was loaded from, indexed by `span.file`;
+    /// only present when imports pulled in other files
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub imports: Vec<Import>,
+    pub citizens: Vec<Citizen>,
+    #[serde(default)]
+    pub schemas: Vec<SchemaDecl>,
+}
+
+impl AstDocument {
+    /// Wrap a parsed document from `source` into a versioned document
+    pub fn new(source: &str, document: Document) -> Self {
+        AstDocument {
+            version: AST_FORMAT_VERSION,
+            source: source.to_string(),
+            files: Vec::new(),
+            imports: document.imports,
+            citizens: document.citizens,
+            schemas: document.schemas,
+        }
+    }
+}
+
+fn parse_value(pair: pest::iterators::Pair<Rule>) -> Result<Spanned<Value>, CldError> {
+    let span = Span::from_pest(pair.as_span());
+    Ok(Spanned::new(parse_raw_value(pair, span)?, span))
+}
+
+fn parse_raw_value(pair: pest::iterators::Pair<Rule>, span: Span) -> Result<Value, CldError> {
     match pair.as_rule() {
         Rule::string => {
-            // Remove quotes from string value
-            let str_content = pair.as_str();
-            if str_content.starts_with("\"\"\"") && str_content.ends_with("\"\"\"") {
-                // Handle triple-quoted strings
-                Ok(Value::String(str_content[3..str_content.len()-3].to_string()))
-            } else if str_content.starts_with("\"") && str_content.ends_with("\"") {
-                // Handle regular quoted strings
-                Ok(Value::String(str_content[1..str_content.len()-1].to_string()))
-            } else {
-                Ok(Value::String(str_content.to_string()))
-            }
+            // Strip delimiters, dedent triple-quoted strings and decode escapes
+            Ok(Value::String(strings::decode_string(pair.as_str(), span)?))
         }
         Rule::number => {
             let num_str = pair.as_str();
-            let number = num_str.parse::<f64>()?;
+            let number = num_str.parse::<f64>().map_err(|e| CldError::InvalidValue {
+                message: format!("Invalid number '{}': {}", num_str, e),
+                span,
+            })?;
             Ok(Value::Number(number))
         }
         Rule::boolean => {
@@ -98,183 +225,369 @@ fn parse_value(pair: pest::iterators::Pair<Rule>) -> Result<Value, Box<dyn std::
             match bool_str {
                 "true" => Ok(Value::Boolean(true)),
                 "false" => Ok(Value::Boolean(false)),
-                _ => Err(format!("Invalid boolean value: {}", bool_str).into()),
+                _ => Err(CldError::InvalidValue {
+                    message: format!("Invalid boolean value: {}", bool_str),
+                    span,
+                }),
             }
         }
         Rule::list => {
             let mut values = Vec::new();
+            // `value` is a silent rule, so the children are the concrete values
             for inner_pair in pair.into_inner() {
-                if inner_pair.as_rule() == Rule::value {
-                    values.push(parse_value(inner_pair)?);
-                }
+                values.push(parse_value(inner_pair)?);
             }
             Ok(Value::List(values))
         }
-        Rule::identifier => {
+        Rule::qualified_name => {
             Ok(Value::Identifier(pair.as_str().to_string()))
         }
-        _ => Err(format!("Unexpected value rule: {:?}, text: {:?}", pair.as_rule(), pair.as_str()).into()),
+        Rule::map => {
+            let mut entries = Fields::new();
+            for entry in pair.into_inner() {
+                entries.push(parse_map_entry(entry)?);
+            }
+            Ok(Value::Map(entries))
+        }
+        _ => Err(CldError::syntax(
+            format!("Unexpected value rule: {:?}, text: {:?}", pair.as_rule(), pair.as_str()),
+            span,
+        )),
+    }
+}
+
+fn parse_decl_mode(pair: &pest::iterators::Pair<Rule>) -> DeclMode {
+    match pair.as_str() {
+        "merge" => DeclMode::Merge,
+        _ => DeclMode::Override,
     }
 }
 
-fn parse_origin(pair: pest::iterators::Pair<Rule>) -> Result<Origin, Box<dyn std::error::Error>> {
+fn parse_field(pair: pest::iterators::Pair<Rule>) -> Result<Field, CldError> {
+    let span = Span::from_pest(pair.as_span());
+    let mut field_inner = pair.into_inner();
+    let key_pair = field_inner
+        .next()
+        .ok_or_else(|| CldError::syntax("Missing field key", span))?;
+    let key_span = Span::from_pest(key_pair.as_span());
+    let written = key_pair.as_str();
+    let key = canonical_field_name(written).to_string();
+    let alias = (key != written).then(|| written.to_string());
+    let value_pair = field_inner
+        .next()
+        .ok_or_else(|| CldError::syntax("Missing field value", span))?;
+    let value = parse_value(value_pair)?;
+    Ok(Field { key, alias, key_span, value })
+}
+
+/// Parse a `key: value` entry of a map value
+///
+/// Map keys are data rather than schema fields, so they are kept as written.
+fn parse_map_entry(pair: pest::iterators::Pair<Rule>) -> Result<Field, CldError> {
+    let span = Span::from_pest(pair.as_span());
+    let mut entry_inner = pair.into_inner();
+    let key_pair = entry_inner
+        .next()
+        .ok_or_else(|| CldError::syntax("Missing map key", span))?;
+    let key_span = Span::from_pest(key_pair.as_span());
+    let key = match key_pair.as_rule() {
+        Rule::string => match parse_raw_value(key_pair, key_span)? {
+            Value::String(key) => key,
+            other => other.to_string(),
+        },
+        _ => key_pair.as_str().to_string(),
+    };
+    let value_pair = entry_inner
+        .next()
+        .ok_or_else(|| CldError::syntax("Missing map value", span))?;
+    let value = parse_value(value_pair)?;
+    Ok(Field { key, alias: None, key_span, value })
+}
+
+fn parse_origin(pair: pest::iterators::Pair<Rule>) -> Result<Origin, CldError> {
+    let span = Span::from_pest(pair.as_span());
     let mut inner = pair.into_inner();
-    let name = inner.next().ok_or("Missing origin name")?.as_str().to_string();
-    let mut fields = std::collections::HashMap::new();
+    let name_pair = inner.next().ok_or_else(|| CldError::syntax("Missing origin name", span))?;
+    let name_span = Span::from_pest(name_pair.as_span());
+    let name = name_pair.as_str().to_string();
+    let mut mode = DeclMode::Declare;
+    let mut fields = Fields::new();
 
     for field in inner {
-        if field.as_rule() == Rule::origin_field {
-            let mut field_inner = field.into_inner();
-            let key = field_inner.next().ok_or("Missing field key")?.as_str().to_string();
-            let value_pair = field_inner.next().ok_or("Missing field value")?;
-            let value = parse_value(value_pair)?;
-            fields.insert(key, value);
+        match field.as_rule() {
+            Rule::decl_mode => mode = parse_decl_mode(&field),
+            Rule::origin_field => fields.push(parse_field(field)?),
+            _ => {}
         }
     }
 
-    Ok(Origin { name, fields })
+    Ok(Origin { name, name_span, mode, fields, span })
 }
 
-fn parse_timeline(pair: pest::iterators::Pair<Rule>) -> Result<Timeline, Box<dyn std::error::Error>> {
+fn parse_timeline(pair: pest::iterators::Pair<Rule>) -> Result<Timeline, CldError> {
+    let span = Span::from_pest(pair.as_span());
     let mut inner = pair.into_inner();
-    let name = inner.next().ok_or("Missing timeline name")?.as_str().to_string();
-    let mut fields = std::collections::HashMap::new();
+    let name_pair = inner.next().ok_or_else(|| CldError::syntax("Missing timeline name", span))?;
+    let name_span = Span::from_pest(name_pair.as_span());
+    let name = name_pair.as_str().to_string();
+    let mut mode = DeclMode::Declare;
+    let mut fields = Fields::new();
 
     for field in inner {
-        if field.as_rule() == Rule::timeline_field {
-            let mut field_inner = field.into_inner();
-            let key = field_inner.next().ok_or("Missing field key")?.as_str().to_string();
-            let value_pair = field_inner.next().ok_or("Missing field value")?;
-            let value = parse_value(value_pair)?;
-            fields.insert(key, value);
+        match field.as_rule() {
+            Rule::decl_mode => mode = parse_decl_mode(&field),
+            Rule::timeline_field => fields.push(parse_field(field)?),
+            _ => {}
         }
     }
 
-    Ok(Timeline { name, fields })
+    Ok(Timeline { name, name_span, mode, fields, span })
 }
 
-fn parse_event(pair: pest::iterators::Pair<Rule>) -> Result<Event, Box<dyn std::error::Error>> {
+fn parse_event(pair: pest::iterators::Pair<Rule>) -> Result<Event, CldError> {
+    let span = Span::from_pest(pair.as_span());
     let mut inner = pair.into_inner();
-    let name = inner.next().ok_or("Missing event name")?.as_str().to_string();
-    let mut fields = std::collections::HashMap::new();
+    let name_pair = inner.next().ok_or_else(|| CldError::syntax("Missing event name", span))?;
+    let name_span = Span::from_pest(name_pair.as_span());
+    let name = name_pair.as_str().to_string();
+    let mut mode = DeclMode::Declare;
+    let mut fields = Fields::new();
 
     for field in inner {
-        if field.as_rule() == Rule::event_field {
-            let mut field_inner = field.into_inner();
-            let key = field_inner.next().ok_or("Missing field key")?.as_str().to_string();
-            let value_pair = field_inner.next().ok_or("Missing field value")?;
-            let value = parse_value(value_pair)?;
-            fields.insert(key, value);
+        match field.as_rule() {
+            Rule::decl_mode => mode = parse_decl_mode(&field),
+            Rule::event_field => fields.push(parse_field(field)?),
+            _ => {}
         }
     }
 
-    Ok(Event { name, fields })
+    Ok(Event { name, name_span, mode, fields, span })
 }
 
-fn parse_core_event(pair: pest::iterators::Pair<Rule>) -> Result<CoreEvent, Box<dyn std::error::Error>> {
+fn parse_core_event(pair: pest::iterators::Pair<Rule>) -> Result<CoreEvent, CldError> {
+    let span = Span::from_pest(pair.as_span());
     let mut inner = pair.into_inner();
-    let name = inner.next().ok_or("Missing core event name")?.as_str().to_string();
-    let mut fields = std::collections::HashMap::new();
+    let name_pair = inner.next().ok_or_else(|| CldError::syntax("Missing core event name", span))?;
+    let name_span = Span::from_pest(name_pair.as_span());
+    let name = name_pair.as_str().to_string();
+    let mut mode = DeclMode::Declare;
+    let mut fields = Fields::new();
 
     for field in inner {
-        if field.as_rule() == Rule::event_field {
-            let mut field_inner = field.into_inner();
-            let key = field_inner.next().ok_or("Missing field key")?.as_str().to_string();
-            let value_pair = field_inner.next().ok_or("Missing field value")?;
-            let value = parse_value(value_pair)?;
-            fields.insert(key, value);
+        match field.as_rule() {
+            Rule::decl_mode => mode = parse_decl_mode(&field),
+            Rule::event_field => fields.push(parse_field(field)?),
+            _ => {}
         }
     }
 
-    Ok(CoreEvent { name, fields })
+    Ok(CoreEvent { name, name_span, mode, fields, span })
 }
 
-fn parse_niche(pair: pest::iterators::Pair<Rule>) -> Result<Niche, Box<dyn std::error::Error>> {
+fn parse_niche(pair: pest::iterators::Pair<Rule>) -> Result<Niche, CldError> {
+    let span = Span::from_pest(pair.as_span());
     let mut inner = pair.into_inner();
-    let name = inner.next().ok_or("Missing niche name")?.as_str().to_string();
-    let mut fields = std::collections::HashMap::new();
+    let name_pair = inner.next().ok_or_else(|| CldError::syntax("Missing niche name", span))?;
+    let name_span = Span::from_pest(name_pair.as_span());
+    let name = name_pair.as_str().to_string();
+    let mut mode = DeclMode::Declare;
+    let mut fields = Fields::new();
 
     for field in inner {
-        if field.as_rule() == Rule::niche_field {
-            let mut field_inner = field.into_inner();
-            let key = field_inner.next().ok_or("Missing field key")?.as_str().to_string();
-            let value_pair = field_inner.next().ok_or("Missing field value")?;
-            let value = parse_value(value_pair)?;
-            fields.insert(key, value);
+        match field.as_rule() {
+            Rule::decl_mode => mode = parse_decl_mode(&field),
+            Rule::niche_field => fields.push(parse_field(field)?),
+            _ => {}
         }
     }
 
-    Ok(Niche { name, fields })
+    Ok(Niche { name, name_span, mode, fields, span })
 }
 
-fn parse_era(pair: pest::iterators::Pair<Rule>) -> Result<Era, Box<dyn std::error::Error>> {
+fn parse_era(pair: pest::iterators::Pair<Rule>) -> Result<Era, CldError> {
+    let span = Span::from_pest(pair.as_span());
     let mut inner = pair.into_inner();
-    let name = inner.next().ok_or("Missing era name")?.as_str().to_string();
-    let mut fields = std::collections::HashMap::new();
+    let name_pair = inner.next().ok_or_else(|| CldError::syntax("Missing era name", span))?;
+    let name_span = Span::from_pest(name_pair.as_span());
+    let name = name_pair.as_str().to_string();
+    let mut mode = DeclMode::Declare;
+    let mut fields = Fields::new();
 
     for field in inner {
-        if field.as_rule() == Rule::era_field {
-            let mut field_inner = field.into_inner();
-            let key = field_inner.next().ok_or("Missing field key")?.as_str().to_string();
-            let value_pair = field_inner.next().ok_or("Missing field value")?;
-            let value = parse_value(value_pair)?;
-            fields.insert(key, value);
+        match field.as_rule() {
+            Rule::decl_mode => mode = parse_decl_mode(&field),
+            Rule::era_field => fields.push(parse_field(field)?),
+            _ => {}
         }
     }
 
-    Ok(Era { name, fields })
+    Ok(Era { name, name_span, mode, fields, span })
 }
 
-fn parse_generator(pair: pest::iterators::Pair<Rule>) -> Result<Generator, Box<dyn std::error::Error>> {
+fn parse_generator(pair: pest::iterators::Pair<Rule>) -> Result<Generator, CldError> {
+    let span = Span::from_pest(pair.as_span());
     let mut inner = pair.into_inner();
-    let name = inner.next().ok_or("Missing generator name")?.as_str().to_string();
-    let mut fields = std::collections::HashMap::new();
+    let name_pair = inner.next().ok_or_else(|| CldError::syntax("Missing generator name", span))?;
+    let name_span = Span::from_pest(name_pair.as_span());
+    let name = name_pair.as_str().to_string();
+    let mut mode = DeclMode::Declare;
+    let mut fields = Fields::new();
 
     for field in inner {
-        if field.as_rule() == Rule::generator_field {
-            let mut field_inner = field.into_inner();
-            let key = field_inner.next().ok_or("Missing field key")?.as_str().to_string();
-            let value_pair = field_inner.next().ok_or("Missing field value")?;
-            let value = parse_value(value_pair)?;
-            fields.insert(key, value);
+        match field.as_rule() {
+            Rule::decl_mode => mode = parse_decl_mode(&field),
+            Rule::generator_field => fields.push(parse_field(field)?),
+            _ => {}
         }
     }
 
-    Ok(Generator { name, fields })
+    Ok(Generator { name, name_span, mode, fields, span })
 }
 
-fn parse_memory(pair: pest::iterators::Pair<Rule>) -> Result<Memory, Box<dyn std::error::Error>> {
+fn parse_memory(pair: pest::iterators::Pair<Rule>) -> Result<Memory, CldError> {
+    let span = Span::from_pest(pair.as_span());
     let mut inner = pair.into_inner();
-    let name = inner.next().ok_or("Missing memory name")?.as_str().to_string();
-    let mut fields = std::collections::HashMap::new();
+    let name_pair = inner.next().ok_or_else(|| CldError::syntax("Missing memory name", span))?;
+    let name_span = Span::from_pest(name_pair.as_span());
+    let name = name_pair.as_str().to_string();
+    let mut mode = DeclMode::Declare;
+    let mut fields = Fields::new();
 
     for field in inner {
-        if field.as_rule() == Rule::memory_field {
-            let mut field_inner = field.into_inner();
-            let key = field_inner.next().ok_or("Missing field key")?.as_str().to_string();
-            let value_pair = field_inner.next().ok_or("Missing field value")?;
-            let value = parse_value(value_pair)?;
-            fields.insert(key, value);
+        match field.as_rule() {
+            Rule::decl_mode => mode = parse_decl_mode(&field),
+            Rule::memory_field => fields.push(parse_field(field)?),
+            _ => {}
         }
     }
 
-    Ok(Memory { name, fields })
+    Ok(Memory { name, name_span, mode, fields, span })
+}
+
+fn parse_immune(pair: pest::iterators::Pair<Rule>) -> Result<Immune, CldError> {
+    let span = Span::from_pest(pair.as_span());
+    let mut inner = pair.into_inner();
+    let name_pair = inner.next().ok_or_else(|| CldError::syntax("Missing immune name", span))?;
+    let name_span = Span::from_pest(name_pair.as_span());
+    let name = name_pair.as_str().to_string();
+    let mut mode = DeclMode::Declare;
+    let mut fields = Fields::new();
+
+    for field in inner {
+        match field.as_rule() {
+            Rule::decl_mode => mode = parse_decl_mode(&field),
+            Rule::immune_field => fields.push(parse_field(field)?),
+            _ => {}
+        }
+    }
+
+    Ok(Immune { name, name_span, mode, fields, span })
+}
+
+fn parse_import(pair: pest::iterators::Pair<Rule>) -> Result<Import, CldError> {
+    let span = Span::from_pest(pair.as_span());
+    let path_pair = pair
+        .into_inner()
+        .next()
+        .ok_or_else(|| CldError::syntax("Missing import path", span))?;
+    let path_span = Span::from_pest(path_pair.as_span());
+    let path = strings::decode_string(path_pair.as_str(), path_span)?;
+    Ok(Import { path, span: path_span })
 }
 
-fn parse_immune(pair: pest::iterators::Pair<Rule>) -> Result<Immune, Box<dyn std::error::Error>> {
+fn parse_schema(pair: pest::iterators::Pair<Rule>) -> Result<SchemaDecl, CldError> {
+    let span = Span::from_pest(pair.as_span());
     let mut inner = pair.into_inner();
-    let name = inner.next().ok_or("Missing immune name")?.as_str().to_string();
-    let mut fields = std::collections::HashMap::new();
+    let kind_pair = inner.next().ok_or_else(|| CldError::syntax("Missing schema kind", span))?;
+    let kind_span = Span::from_pest(kind_pair.as_span());
+    let kind = kind_pair.as_str().to_string();
+    let mut fields = Vec::new();
 
     for field in inner {
-        if field.as_rule() == Rule::immune_field {
-            let mut field_inner = field.into_inner();
-            let key = field_inner.next().ok_or("Missing field key")?.as_str().to_string();
-            let value_pair = field_inner.next().ok_or("Missing field value")?;
-            let value = parse_value(value_pair)?;
-            fields.insert(key, value);
+        if field.as_rule() == Rule::schema_field {
+            fields.push(parse_schema_field(field)?);
         }
     }
 
-    Ok(Immune { name, fields })
-}
\ No newline at end of file
+    Ok(SchemaDecl { kind, kind_span, fields, span })
+}
+
+fn parse_schema_field(pair: pest::iterators::Pair<Rule>) -> Result<FieldSchema, CldError> {
+    let span = Span::from_pest(pair.as_span());
+    let mut inner = pair.into_inner();
+    let name_pair = inner.next().ok_or_else(|| CldError::syntax("Missing schema field name", span))?;
+    let name_span = Span::from_pest(name_pair.as_span());
+    let name = canonical_field_name(name_pair.as_str()).to_string();
+    let type_pair = inner.next().ok_or_else(|| CldError::syntax("Missing schema field type", span))?;
+    let ty = parse_field_type(type_pair)?;
+    let mut required = false;
+
+    for modifier in inner {
+        required = modifier.as_str() == "required";
+    }
+
+    Ok(FieldSchema { name, ty, required, span: Some(name_span) })
+}
+
+fn parse_field_type(pair: pest::iterators::Pair<Rule>) -> Result<FieldType, CldError> {
+    let span = Span::from_pest(pair.as_span());
+    // `field_type` wraps exactly one of the concrete type rules
+    let pair = match pair.as_rule() {
+        Rule::field_type => pair
+            .into_inner()
+            .next()
+            .ok_or_else(|| CldError::syntax("Missing field type", span))?,
+        _ => pair,
+    };
+
+    match pair.as_rule() {
+        Rule::number_type => {
+            let (mut min, mut max) = (None, None);
+            if let Some(range) = pair.into_inner().next() {
+                let dots = range.as_span().start() + range.as_str().find("..").unwrap_or(0);
+                for bound in range.into_inner() {
+                    let bound_span = Span::from_pest(bound.as_span());
+                    let value = parse_raw_value(bound, bound_span)?.as_number();
+                    // Either bound may be omitted, so tell them apart by position
+                    if bound_span.start < dots {
+                        min = value;
+                    } else {
+                        max = value;
+                    }
+                }
+            }
+            if let (Some(min), Some(max)) = (min, max) {
+                if min > max {
+                    return Err(CldError::rule(
+                        "!Schema",
+                        format!("empty number range {}..{}", min, max),
+                        Some(span),
+                    ));
+                }
+            }
+            Ok(FieldType::Number { min, max })
+        }
+        Rule::list_type => {
+            let item = pair
+                .into_inner()
+                .next()
+                .ok_or_else(|| CldError::syntax("Missing list item type", span))?;
+            Ok(FieldType::List { item: Box::new(parse_field_type(item)?) })
+        }
+        Rule::ref_type => Ok(FieldType::Ref {
+            kinds: pair.into_inner().map(|kind| kind.as_str().to_string()).collect(),
+        }),
+        Rule::simple_type => match pair.as_str() {
+            "any" => Ok(FieldType::Any),
+            "string" => Ok(FieldType::String),
+            "text" => Ok(FieldType::Text),
+            "boolean" => Ok(FieldType::Boolean),
+            "identifier" => Ok(FieldType::Identifier),
+            "kind" => Ok(FieldType::Kind),
+            "map" => Ok(FieldType::Map),
+            "emotion" => Ok(FieldType::Emotion),
+            other => Err(CldError::syntax(format!("Unknown field type: {}", other), span)),
+        },
+        other => Err(CldError::syntax(format!("Unexpected type rule: {:?}", other), span)),
+    }
+}
@@ -0,0 +1,228 @@
+//! Decoding of string literals
+//!
+//! Three forms are supported:
+//!
+//! - `"..."` with escape sequences
+//! - `"""..."""`, which drops a leading and trailing blank line and removes
+//!   the indentation shared by all non-blank lines before decoding escapes
+//! - `r"..."` / `r#"..."#`, taken verbatim
+
+use crate::error::CldError;
+use crate::span::Span;
+
+/// Decode a string literal, including its delimiters, into its value
+///
+/// `span` must cover exactly `literal`; it is used to point invalid escape
+/// sequences at their position in the source.
+pub(super) fn decode_string(literal: &str, span: Span) -> Result<String, CldError> {
+    if let Some(rest) = literal.strip_prefix('r') {
+        let hashes = rest.len() - rest.trim_start_matches('#').len();
+        Ok(literal[hashes + 2..literal.len() - hashes - 1].to_string())
+    } else if literal.len() >= 6 && literal.starts_with("\"\"\"") {
+        decode_triple_quoted(literal, span)
+    } else {
+        unescape(literal, 1, literal.len() - 1, span)
+    }
+}
+
+/// Dedent the body of a triple-quoted literal, then decode each line
+fn decode_triple_quoted(literal: &str, span: Span) -> Result<String, CldError> {
+    // (start, end) byte ranges of each line of the body within `literal`
+    let mut lines = Vec::new();
+    let mut start = 3;
+    for line in literal[3..literal.len() - 3].split('\n') {
+        let end = start + line.trim_end_matches('\r').len();
+        lines.push((start, end));
+        start += line.len() + 1;
+    }
+
+    let is_blank = |&(start, end): &(usize, usize)| literal[start..end].trim().is_empty();
+    if lines.len() > 1 && lines.first().is_some_and(is_blank) {
+        lines.remove(0);
+    }
+    if lines.len() > 1 && lines.last().is_some_and(is_blank) {
+        lines.pop();
+    }
+
+    let indent = lines
+        .iter()
+        .filter(|line| !is_blank(line))
+        .map(|&(start, end)| {
+            let text = &literal[start..end];
+            &text[..text.len() - text.trim_start_matches([' ', '\t']).len()]
+        })
+        .reduce(|common, indent| {
+            let shared = common
+                .char_indices()
+                .zip(indent.chars())
+                .find(|((_, a), b)| a != b)
+                .map(|((index, _), _)| index)
+                .unwrap_or(common.len().min(indent.len()));
+            &common[..shared]
+        })
+        .unwrap_or("");
+
+    let mut decoded = Vec::with_capacity(lines.len());
+    for line in &lines {
+        if is_blank(line) {
+            decoded.push(String::new());
+        } else {
+            decoded.push(unescape(literal, line.0 + indent.len(), line.1, span)?);
+        }
+    }
+    Ok(decoded.join("\n"))
+}
+
+/// Decode escape sequences in `literal[from..to]`
+///
+/// Supported escapes are `\\`, `\"`, `\'`, `\n`, `\r`, `\t`, `\0` and
+/// `\u{XXXX}` with one to six hex digits.
+fn unescape(literal: &str, from: usize, to: usize, span: Span) -> Result<String, CldError> {
+    let mut out = String::with_capacity(to - from);
+    let mut chars = literal[from..to].char_indices().map(|(i, c)| (i + from, c)).peekable();
+
+    while let Some((index, c)) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        let invalid = |end: usize, message: String| CldError::InvalidValue {
+            message,
+            span: span.sub(literal, index, end),
+        };
+        let Some((escape_index, escape)) = chars.next() else {
+            return Err(invalid(to, "unterminated escape sequence".to_string()));
+        };
+        let escape_end = escape_index + escape.len_utf8();
+
+        match escape {
+            '\\' => out.push('\\'),
+            '"' => out.push('"'),
+            '\'' => out.push('\''),
+            'n' => out.push('\n'),
+            'r' => out.push('\r'),
+            't' => out.push('\t'),
+            '0' => out.push('\0'),
+            'u' => {
+                if chars.next_if(|&(_, c)| c == '{').is_none() {
+                    return Err(invalid(
+                        escape_end,
+                        "unicode escape must be written as \\u{XXXX}".to_string(),
+                    ));
+                }
+                let mut digits = String::new();
+                let mut end = escape_end + 1;
+                let mut closed = false;
+                for (i, c) in chars.by_ref() {
+                    end = i + c.len_utf8();
+                    if c == '}' {
+                        closed = true;
+                        break;
+                    }
+                    digits.push(c);
+                }
+                let code = u32::from_str_radix(&digits, 16).ok();
+                match (closed, digits.len(), code.and_then(char::from_u32)) {
+                    (true, 1..=6, Some(c)) => out.push(c),
+                    (false, _, _) => {
+                        return Err(invalid(end, "unterminated unicode escape".to_string()))
+                    }
+                    _ => {
+                        return Err(invalid(
+                            end,
+                            format!("invalid unicode escape '\\u{{{}}}'", digits),
+                        ))
+                    }
+                }
+            }
+            other => {
+                return Err(invalid(
+                    escape_end,
+                    format!("invalid escape sequence '\\{}'", other.escape_default()),
+                ))
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The span of `literal` when it starts at line 1, column 1
+    fn span_of(literal: &str) -> Span {
+        Span {
+            file: 0,
+            start: 0,
+            end: literal.len(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn decode(literal: &str) -> Result<String, CldError> {
+        decode_string(literal, span_of(literal))
+    }
+
+    /// The span an invalid literal is reported at
+    fn error_span(literal: &str) -> Span {
+        match decode(literal) {
+            Err(CldError::InvalidValue { span, .. }) => span,
+            other => panic!("expected an invalid value, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decodes_escapes() {
+        assert_eq!(decode(r#""a\tb\nc""#).unwrap(), "a\tb\nc");
+        assert_eq!(decode(r#""\\ \" \' \r \0""#).unwrap(), "\\ \" ' \r \0");
+        assert_eq!(decode(r#""\u{41}\u{1F600}""#).unwrap(), "A\u{1F600}");
+        assert_eq!(decode(r#""星\u{661f}""#).unwrap(), "星星");
+    }
+
+    #[test]
+    fn reports_invalid_escapes_at_their_position() {
+        let span = error_span(r#""ab\qc""#);
+        assert_eq!((span.start, span.end, span.column), (3, 5, 4));
+
+        // Columns count characters, not bytes
+        let span = error_span(r#""星\x""#);
+        assert_eq!((span.start, span.column), (4, 3));
+
+        let span = error_span(r#""\u{110000}""#);
+        assert_eq!((span.start, span.end), (1, 11));
+        assert!(matches!(decode(r#""\u{}""#), Err(CldError::InvalidValue { .. })));
+        assert!(matches!(decode(r#""\u41""#), Err(CldError::InvalidValue { .. })));
+        assert!(matches!(decode(r#""\u{41""#), Err(CldError::InvalidValue { .. })));
+    }
+
+    #[test]
+    fn raw_strings_are_verbatim() {
+        assert_eq!(decode(r#"r"C:\path\n""#).unwrap(), r"C:\path\n");
+        assert_eq!(decode(r###"r##"say "#hi""##"###).unwrap(), r##"say "#hi""##);
+    }
+
+    #[test]
+    fn triple_quoted_strings_are_dedented() {
+        let literal = "\"\"\"\n    first\n      indented\n\n    last\n    \"\"\"";
+        assert_eq!(decode(literal).unwrap(), "first\n  indented\n\nlast");
+    }
+
+    #[test]
+    fn triple_quoted_strings_on_one_line() {
+        // A lone line shares its indentation with itself
+        assert_eq!(decode(r#""""  one line""""#).unwrap(), "one line");
+        assert_eq!(decode("\"\"\"\r\n  a\\tb\r\n  \"\"\"").unwrap(), "a\tb");
+    }
+
+    #[test]
+    fn triple_quoted_errors_point_into_the_right_line() {
+        let literal = "\"\"\"\n  ok\n  bad \\q\n\"\"\"";
+        let span = error_span(literal);
+        assert_eq!((span.line, span.column), (3, 7));
+        assert_eq!(&literal[span.start..span.end], "\\q");
+    }
+}
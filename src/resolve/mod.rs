@@ -0,0 +1,177 @@
+//! Reference resolution
+//!
+//! Fields whose schema type is `ref(Kind | ...)` (or a list of such) hold
+//! identifiers naming other citizens, e.g. `origin: hello_cosmos` in a
+//! `@Timeline` or `start: big_bang` in an `@Era`. Resolution binds each of
+//! these identifiers to the citizen it names and records the binding as an
+//! edge of a [`ReferenceGraph`].
+//!
+//! Names are looked up in the namespace of the referring citizen first and
+//! then in each enclosing namespace (see [`World::lookup_in`]), so
+//! `start: war` inside `@Namespace north` finds `north::war` before `war`.
+
+use crate::citizens::{namespace_of, CitizenData, Value};
+use crate::error::CldError;
+use crate::schema::{FieldType, CITIZEN_KINDS};
+use crate::span::{Span, Spanned};
+use crate::world::World;
+use serde::{Deserialize, Serialize};
+
+/// Identifies one citizen of a world
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct CitizenRef {
+    /// Citizen kind, e.g. `"CoreEvent"`
+    pub kind: String,
+    pub name: String,
+}
+
+impl CitizenRef {
+    /// Refer to `citizen`
+    pub fn of(citizen: &dyn CitizenData) -> Self {
+        CitizenRef {
+            kind: citizen.kind().to_string(),
+            name: citizen.name().to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for CitizenRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}[{}]", self.kind, self.name)
+    }
+}
+
+/// A resolved identifier: `from.field` names `to`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Reference {
+    pub from: CitizenRef,
+    /// Canonical key of the field holding the identifier
+    pub field: String,
+    pub to: CitizenRef,
+    /// Where the identifier appears
+    pub span: Span,
+}
+
+/// All resolved references of a world, in the order they were found
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReferenceGraph {
+    pub references: Vec<Reference>,
+}
+
+impl ReferenceGraph {
+    /// References made by `citizen`
+    pub fn outgoing<'a>(&'a self, citizen: &'a CitizenRef) -> impl Iterator<Item = &'a Reference> + 'a {
+        self.references.iter().filter(move |reference| &reference.from == citizen)
+    }
+
+    /// References made to `citizen`
+    pub fn incoming<'a>(&'a self, citizen: &'a CitizenRef) -> impl Iterator<Item = &'a Reference> + 'a {
+        self.references.iter().filter(move |reference| &reference.to == citizen)
+    }
+
+    /// Citizens that `citizen.field` refers to
+    pub fn targets<'a>(&'a self, citizen: &'a CitizenRef, field: &'a str) -> impl Iterator<Item = &'a CitizenRef> + 'a {
+        self.outgoing(citizen)
+            .filter(move |reference| reference.field == field)
+            .map(|reference| &reference.to)
+    }
+}
+
+/// Resolve every reference-typed field of every citizen in `world`
+///
+/// Identifiers that name no citizen of the expected kinds are reported as
+/// unresolved, and identifiers that name citizens of several expected kinds
+/// as ambiguous; neither produces an edge.
+pub fn resolve(world: &World) -> (ReferenceGraph, Vec<CldError>) {
+    let mut graph = ReferenceGraph::default();
+    let mut errors = Vec::new();
+
+    for citizen in world.citizens() {
+        let from = CitizenRef::of(citizen);
+        for field in citizen.fields() {
+            let Some(declared) = world.schema.field(citizen.kind(), &field.key) else {
+                continue;
+            };
+            let context = Context {
+                world,
+                from: &from,
+                field: &field.key,
+            };
+            context.resolve_value(&declared.ty, &field.value, &mut graph, &mut errors);
+        }
+    }
+
+    (graph, errors)
+}
+
+/// The field whose value is being resolved
+struct Context<'a> {
+    world: &'a World,
+    from: &'a CitizenRef,
+    field: &'a str,
+}
+
+impl Context<'_> {
+    fn resolve_value(
+        &self,
+        ty: &FieldType,
+        value: &Spanned<Value>,
+        graph: &mut ReferenceGraph,
+        errors: &mut Vec<CldError>,
+    ) {
+        match (ty, &value.node) {
+            (FieldType::Ref { kinds }, Value::Identifier(name)) => {
+                match self.bind(kinds, name, value.span) {
+                    Ok(to) => graph.references.push(Reference {
+                        from: self.from.clone(),
+                        field: self.field.to_string(),
+                        to,
+                        span: value.span,
+                    }),
+                    Err(e) => errors.push(e),
+                }
+            }
+            (FieldType::Kind, Value::Identifier(kind)) if !CITIZEN_KINDS.contains(&kind.as_str()) => {
+                errors.push(CldError::UnresolvedReference {
+                    name: kind.clone(),
+                    expected: "citizen kind".to_string(),
+                    field: format!("{}.{}", self.from, self.field),
+                    span: value.span,
+                });
+            }
+            (FieldType::List { item }, Value::List(items)) => {
+                for element in items {
+                    self.resolve_value(item, element, graph, errors);
+                }
+            }
+            // Values of the wrong type are reported by the schema check
+            _ => {}
+        }
+    }
+
+    /// Find the single citizen of one of `kinds` that `name` refers to from
+    /// the namespace of the referring citizen
+    fn bind(&self, kinds: &[String], name: &str, span: Span) -> Result<CitizenRef, CldError> {
+        let matching = self.world.lookup_in(namespace_of(&self.from.name), name, kinds);
+
+        match matching.as_slice() {
+            [only] => Ok(only.clone()),
+            [] => Err(CldError::UnresolvedReference {
+                name: name.to_string(),
+                expected: kinds
+                    .iter()
+                    .map(|kind| format!("@{}", kind))
+                    .collect::<Vec<_>>()
+                    .join(" or "),
+                field: format!("{}.{}", self.from, self.field),
+                span,
+            }),
+            several => Err(CldError::AmbiguousReference {
+                name: name.to_string(),
+                field: format!("{}.{}", self.from, self.field),
+                candidates: several.iter().map(|candidate| candidate.to_string()).collect(),
+                span,
+            }),
+        }
+    }
+}
@@ -0,0 +1,326 @@
+//! World entropy
+//!
+//! Entropy starts at `Origin.entropy` and changes by each fired event's
+//! `entropy_change`. The Origin may also configure:
+//!
+//! ```text
+//! @Origin[cosmos] {
+//!     entropy: 0.5
+//!     min_entropy: 0.1
+//!     max_entropy: 0.9
+//!     entropy_decay: 0.05
+//!     entropy_clamp: reject
+//! }
+//! ```
+//!
+//! `min_entropy` and `max_entropy` are the global bounds (0 and 1 by
+//! default). `entropy_decay` moves entropy that fraction of the way down to
+//! `min_entropy` at the start of every tick. `entropy_clamp` decides what
+//! happens to a change that would leave the bounds: `saturate` (the
+//! default) stops at the bound, `reject` refuses the change, and `none`
+//! lets entropy leave the bounds. Unless it is `none`, `entropy` must start
+//! within the bounds.
+
+use crate::error::CldError;
+use crate::typed::TypedOrigin;
+use crate::world::World;
+
+/// What happens to a change that would take entropy outside its bounds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ClampPolicy {
+    /// Stop at the bound
+    #[default]
+    Saturate,
+    /// Refuse the change
+    Reject,
+    /// Apply the change anyway
+    Unbounded,
+}
+
+impl ClampPolicy {
+    /// The policy called `name` in `entropy_clamp`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "saturate" => Some(ClampPolicy::Saturate),
+            "reject" => Some(ClampPolicy::Reject),
+            "none" => Some(ClampPolicy::Unbounded),
+            _ => None,
+        }
+    }
+}
+
+/// World entropy over the ticks of a run
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntropyModel {
+    pub min: f64,
+    pub max: f64,
+    /// Fraction of the distance to `min` lost at the start of every tick
+    pub decay: f64,
+    pub policy: ClampPolicy,
+    /// The starting value as configured, before any bounds apply
+    initial: f64,
+    value: f64,
+    /// Entropy at the end of each tick, starting with the initial value
+    history: Vec<f64>,
+}
+
+impl EntropyModel {
+    /// Start at `initial` within the default bounds of 0..1, without decay
+    pub fn new(initial: f64) -> Self {
+        EntropyModel {
+            min: 0.0,
+            max: 1.0,
+            decay: 0.0,
+            policy: ClampPolicy::Saturate,
+            initial,
+            value: initial,
+            history: vec![initial],
+        }
+    }
+
+    /// The model configured by `origin`, or the default one starting at 0
+    ///
+    /// Settings that are unset or invalid keep their defaults; validation
+    /// reports the invalid ones.
+    pub fn from_origin(origin: Option<&TypedOrigin>) -> Self {
+        let Some(origin) = origin else {
+            return Self::new(0.0);
+        };
+        let mut model = Self::new(origin.entropy.unwrap_or(0.0));
+        let min = origin.min_entropy.unwrap_or(model.min);
+        let max = origin.max_entropy.unwrap_or(model.max);
+        if min <= max {
+            model = model.with_bounds(min, max);
+        }
+        if let Some(decay) = origin.entropy_decay {
+            model = model.with_decay(decay);
+        }
+        if let Some(policy) = origin.entropy_clamp.as_deref().and_then(ClampPolicy::from_name) {
+            model = model.with_policy(policy);
+        }
+        model
+    }
+
+    /// Use `min..max` as the global bounds
+    ///
+    /// Unless the policy is `Unbounded`, the current value is brought
+    /// within them.
+    pub fn with_bounds(mut self, min: f64, max: f64) -> Self {
+        self.min = min;
+        self.max = max;
+        self.restart();
+        self
+    }
+
+    /// Lose `decay` of the entropy above `min` at the start of every tick
+    ///
+    /// `decay` is clamped to `0..=1`.
+    pub fn with_decay(mut self, decay: f64) -> Self {
+        self.decay = decay.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Handle changes that would leave the bounds with `policy`
+    ///
+    /// Like [`Self::with_bounds`], this brings the current value within the
+    /// bounds unless the policy is `Unbounded`.
+    pub fn with_policy(mut self, policy: ClampPolicy) -> Self {
+        self.policy = policy;
+        self.restart();
+        self
+    }
+
+    /// Bring the initial value within bounds after a change of settings
+    fn restart(&mut self) {
+        self.value = match self.policy {
+            ClampPolicy::Unbounded => self.initial,
+            _ => self.initial.clamp(self.min, self.max),
+        };
+        self.history = vec![self.value];
+    }
+
+    /// The current entropy
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    /// Entropy once `delta` is applied, or `None` if the policy refuses it
+    pub fn propose(&self, delta: f64) -> Option<f64> {
+        let next = self.value + delta;
+        match self.policy {
+            ClampPolicy::Saturate => Some(next.clamp(self.min, self.max)),
+            ClampPolicy::Reject if next < self.min || next > self.max => None,
+            ClampPolicy::Reject | ClampPolicy::Unbounded => Some(next),
+        }
+    }
+
+    /// Apply `delta`, returning false if the policy refuses it
+    pub fn apply(&mut self, delta: f64) -> bool {
+        match self.propose(delta) {
+            Some(next) => {
+                self.value = next;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Start a tick by applying decay
+    pub fn begin_tick(&mut self) {
+        self.value -= (self.value - self.min) * self.decay;
+    }
+
+    /// End a tick by recording the current value
+    pub fn end_tick(&mut self) {
+        self.history.push(self.value);
+    }
+
+    /// Entropy at the end of `tick`; tick 0 is the initial value
+    pub fn at(&self, tick: u64) -> Option<f64> {
+        self.history.get(tick as usize).copied()
+    }
+
+    /// Entropy at the end of every tick so far, starting with the initial
+    /// value
+    pub fn history(&self) -> &[f64] {
+        &self.history
+    }
+}
+
+/// Check the Origin's entropy settings
+pub fn validate_entropy(world: &World) -> Vec<CldError> {
+    let mut errors = Vec::new();
+    let Some(origin) = &world.origin else {
+        return errors;
+    };
+
+    let number = |key| origin.fields.get(key).and_then(|value| value.as_number());
+    if let (Some(min), Some(max)) = (number("min_entropy"), number("max_entropy")) {
+        if min > max {
+            errors.push(CldError::rule(
+                "!Entropy",
+                format!("min_entropy {} is greater than max_entropy {}", min, max),
+                origin.fields.field("min_entropy").map(|field| field.key_span),
+            ));
+        }
+    }
+
+    // Outside 0..1 is a schema error; within it, the start must respect the
+    // Origin's own bounds unless entropy may leave them
+    let (min, max) = (number("min_entropy").unwrap_or(0.0), number("max_entropy").unwrap_or(1.0));
+    let unbounded = origin
+        .fields
+        .get("entropy_clamp")
+        .and_then(|value| value.as_identifier().or(value.as_string()))
+        .is_some_and(|name| ClampPolicy::from_name(name) == Some(ClampPolicy::Unbounded));
+    if let Some(field) = origin.fields.field("entropy") {
+        let initial = field.value.as_number().filter(|initial| (0.0..=1.0).contains(initial));
+        if let Some(initial) = initial.filter(|initial| min <= max && !unbounded && !(min..=max).contains(initial)) {
+            errors.push(CldError::rule(
+                "!Entropy",
+                format!("entropy {} is outside min_entropy..max_entropy ({}..{})", initial, min, max),
+                Some(field.value.span),
+            ));
+        }
+    }
+
+    if let Some(field) = origin.fields.field("entropy_clamp") {
+        let name = field.value.as_identifier().or(field.value.as_string());
+        if let Some(name) = name.filter(|name| ClampPolicy::from_name(name).is_none()) {
+            errors.push(CldError::rule(
+                "!Entropy",
+                format!("Unknown entropy_clamp '{}' (expected saturate, reject or none)", name),
+                Some(field.value.span),
+            ));
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::CLDParser;
+
+    fn model(policy: ClampPolicy) -> EntropyModel {
+        EntropyModel::new(0.5).with_bounds(0.2, 0.8).with_policy(policy)
+    }
+
+    #[test]
+    fn saturate_stops_at_the_bounds() {
+        let mut entropy = model(ClampPolicy::Saturate);
+        assert!(entropy.apply(0.5));
+        assert_eq!(entropy.value(), 0.8);
+        assert!(entropy.apply(-1.0));
+        assert_eq!(entropy.value(), 0.2);
+    }
+
+    #[test]
+    fn reject_refuses_changes_that_leave_the_bounds() {
+        let mut entropy = model(ClampPolicy::Reject);
+        assert_eq!(entropy.propose(0.4), None);
+        assert!(!entropy.apply(0.4));
+        assert_eq!(entropy.value(), 0.5);
+        // Reaching a bound exactly is allowed
+        assert!(entropy.apply(0.3));
+        assert_eq!(entropy.value(), 0.8);
+    }
+
+    #[test]
+    fn none_lets_entropy_leave_the_bounds() {
+        let mut entropy = model(ClampPolicy::Unbounded);
+        assert!(entropy.apply(0.7));
+        assert_eq!(entropy.value(), 1.2);
+        // Nor is the starting value brought within them
+        assert_eq!(EntropyModel::new(0.9).with_bounds(0.2, 0.8).with_policy(ClampPolicy::Unbounded).value(), 0.9);
+        assert_eq!(EntropyModel::new(0.9).with_bounds(0.2, 0.8).value(), 0.8);
+    }
+
+    #[test]
+    fn decay_moves_entropy_towards_the_minimum_each_tick() {
+        let mut entropy = EntropyModel::new(1.0).with_bounds(0.2, 1.0).with_decay(0.5);
+        for _ in 0..2 {
+            entropy.begin_tick();
+            entropy.end_tick();
+        }
+        assert_eq!(entropy.history(), [1.0, 0.6, 0.4]);
+        assert_eq!(EntropyModel::new(1.0).with_decay(2.0).decay, 1.0);
+    }
+
+    #[test]
+    fn history_is_looked_up_by_tick() {
+        let mut entropy = EntropyModel::new(0.1);
+        for delta in [0.2, 0.3] {
+            entropy.begin_tick();
+            entropy.apply(delta);
+            entropy.end_tick();
+        }
+        assert_eq!(entropy.at(0), Some(0.1));
+        assert_eq!(entropy.at(2).map(|value| (value * 10.0).round()), Some(6.0));
+        assert_eq!(entropy.at(3), None);
+        assert_eq!(entropy.history().len(), 3);
+    }
+
+    fn messages(source: &str) -> Vec<String> {
+        let document = CLDParser::parse_document(source).expect("test source parses");
+        let (world, errors) = World::build_document(document);
+        assert!(errors.is_empty(), "{:?}", errors);
+        validate_entropy(&world).iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn the_start_must_lie_within_the_bounds() {
+        let errors = messages("@Origin[o] { entropy: 0.9 min_entropy: 0.1 max_entropy: 0.5 }");
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(errors[0].contains("entropy 0.9 is outside"), "{}", errors[0]);
+
+        assert!(messages("@Origin[o] { entropy: 0.5 min_entropy: 0.1 max_entropy: 0.5 }").is_empty());
+        assert!(messages("@Origin[o] { entropy: 0.9 max_entropy: 0.5 entropy_clamp: none }").is_empty());
+    }
+
+    #[test]
+    fn bounds_and_policies_are_checked() {
+        let errors = messages("@Origin[o] { min_entropy: 0.6 max_entropy: 0.4 entropy_clamp: wrap }");
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+    }
+}
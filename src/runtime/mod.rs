@@ -0,0 +1,645 @@
+//! Tick-based simulation of a world
+//!
+//! A [`Simulation`] plays one timeline's history forward, one event per
+//! tick. An event whose `requires` expression does not hold waits, and is
+//! tried again on the next tick; one that still does not hold after
+//! [`DEFAULT_PATIENCE`] ticks of waiting (see [`Simulation::with_patience`])
+//! is skipped, so it cannot hold up the rest of the history. Each fired event applies its
+//! `entropy_change` to world entropy (see [`EntropyModel`]) and its
+//! `effects` to the world's variables: numbers are added to the current
+//! value, expressions are evaluated against the state before the event and
+//! their values assigned, and anything else replaces the current value. An
+//! event that breaks the rules of the era it falls in is refused and leaves
+//! the state untouched, as is one whose entropy change the model rejects.
+//! Once the history is exhausted, further ticks are idle.
+//!
+//! Randomness comes from a seeded [`Rng`] with one named stream per
+//! subsystem; see [`rng`].
+//!
+//! Worlds should be validated first; the simulation itself never fails, but
+//! skips references that do not resolve.
+
+use crate::citizens::Value;
+use crate::era::{Breach, EraInterval, EraRules, Eras};
+use crate::expr::{compile_effects, compile_requires, eval, Effect, Env, Expr, ExprValue, Variables};
+use crate::resolve::{CitizenRef, ReferenceGraph};
+use crate::timeline::{Branches, CausalGraph};
+use crate::typed::{lower_world, TypedWorld};
+use crate::world::World;
+use std::collections::{BTreeMap, HashMap};
+
+pub mod entropy;
+pub mod rng;
+
+pub use entropy::{validate_entropy, ClampPolicy, EntropyModel};
+pub use rng::{Rng, Stream};
+
+/// Ticks an event may wait for its `requires` before it is skipped
+pub const DEFAULT_PATIENCE: u64 = 10;
+
+/// The mutable state of a running world
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorldState {
+    /// Number of ticks run so far
+    pub tick: u64,
+    /// The era of the most recent tick
+    pub era: Option<String>,
+    /// Current world entropy
+    pub entropy: f64,
+    /// Events fired so far, in order
+    pub fired: Vec<CitizenRef>,
+    /// Values accumulated from event `effects`, by key
+    pub variables: BTreeMap<String, Value>,
+}
+
+/// What happened during one tick
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    /// The event fired
+    Fired,
+    /// The event's `requires` does not hold yet, or divides by zero; it is
+    /// tried again next tick
+    Waiting,
+    /// The event's `chance` came up against it, so it did not happen
+    Missed,
+    /// The event's `requires` still did not hold after waiting as long as
+    /// the simulation's patience allows, or one of its `effects` divides by
+    /// zero, so the event was passed over
+    Skipped,
+    /// The event broke the rules of the active era and did not fire
+    Refused(Vec<Breach>),
+    /// The event's entropy change would leave the global bounds, and the
+    /// entropy model rejects such changes
+    OutOfBounds,
+    /// There was no event to fire
+    Idle,
+}
+
+/// The record of one tick
+#[derive(Debug, Clone, PartialEq)]
+pub struct TickRecord {
+    /// The tick, counting from 1
+    pub tick: u64,
+    pub era: Option<String>,
+    /// The event due at this tick, if any
+    pub event: Option<CitizenRef>,
+    pub outcome: Outcome,
+    /// World entropy at the end of the tick
+    pub entropy: f64,
+}
+
+/// An event's compiled `requires`, and its `effects` by variable
+type Compiled = (Option<Expr>, Vec<(String, Effect)>);
+
+/// A world being run tick by tick
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    timeline: Option<CitizenRef>,
+    /// Events in the order they are due, one per tick
+    schedule: Vec<CitizenRef>,
+    /// Position in `schedule` of the next event to try
+    next: usize,
+    /// Ticks the next event has waited so far
+    waited: u64,
+    /// Ticks an event may wait before it is skipped
+    patience: u64,
+    typed: TypedWorld,
+    variables: Variables,
+    /// Compiled `requires` and `effects` of the scheduled events
+    compiled: HashMap<CitizenRef, Compiled>,
+    /// Eras placed on the simulated timeline, with their rules
+    eras: Vec<(EraInterval, EraRules)>,
+    state: WorldState,
+    history: Vec<TickRecord>,
+    entropy: EntropyModel,
+    rng: Rng,
+}
+
+impl Simulation {
+    /// Prepare `world` to run on its first timeline with a history
+    ///
+    /// A world without such a timeline runs all of its events in causal
+    /// order instead; see [`causal_schedule`].
+    pub fn new(world: &World) -> Self {
+        let references = world.reference_graph();
+        let branches = Branches::build(world, &references, &mut Vec::new());
+        let timeline = branches
+            .iter()
+            .find(|branch| !branch.sequence.is_empty())
+            .map(|branch| branch.timeline.clone());
+        match timeline {
+            Some(timeline) => Self::with_timeline(world, &references, &branches, timeline),
+            None => {
+                let schedule = causal_schedule(world, &references);
+                Self::with_schedule(world, None, schedule, Vec::new())
+            }
+        }
+    }
+
+    /// Prepare `world` to run on the timeline called `name`
+    ///
+    /// Returns `None` if the world has no such timeline.
+    pub fn on_timeline(world: &World, name: &str) -> Option<Self> {
+        let references = world.reference_graph();
+        let branches = Branches::build(world, &references, &mut Vec::new());
+        let timeline = branches.iter().find(|branch| branch.timeline.name == name)?.timeline.clone();
+        Some(Self::with_timeline(world, &references, &branches, timeline))
+    }
+
+    fn with_timeline(world: &World, references: &ReferenceGraph, branches: &Branches, timeline: CitizenRef) -> Self {
+        let eras = Eras::build(world, references, branches, &mut Vec::new());
+        let placed = eras.on_timeline(&timeline).cloned().collect();
+        let schedule = branches.get(&timeline).map(|branch| branch.sequence.clone()).unwrap_or_default();
+        Self::with_schedule(world, Some(timeline), schedule, placed)
+    }
+
+    fn with_schedule(
+        world: &World,
+        timeline: Option<CitizenRef>,
+        schedule: Vec<CitizenRef>,
+        placed: Vec<EraInterval>,
+    ) -> Self {
+        // Fields of the wrong type are reported by validation
+        let (typed, _) = lower_world(world);
+        let eras = placed
+            .into_iter()
+            .filter_map(|interval| {
+                let rules = EraRules::of(typed.eras.get(&interval.era.name)?);
+                Some((interval, rules))
+            })
+            .collect();
+        let entropy = EntropyModel::from_origin(typed.origin.as_ref());
+        // Expressions that do not check are reported by validation
+        let (variables, _) = Variables::infer(world);
+        let compiled = schedule
+            .iter()
+            .filter_map(|event| {
+                let citizen = world.citizen(&event.kind, &event.name)?;
+                let requires = compile_requires(world, &variables, citizen);
+                let effects = compile_effects(world, &variables, citizen);
+                Some((event.clone(), (requires, effects)))
+            })
+            .collect();
+        let seed = typed.origin.as_ref().and_then(|origin| origin.seed).unwrap_or(0);
+        Simulation {
+            timeline,
+            schedule,
+            next: 0,
+            waited: 0,
+            patience: DEFAULT_PATIENCE,
+            typed,
+            variables,
+            compiled,
+            eras,
+            state: WorldState {
+                tick: 0,
+                era: None,
+                entropy: entropy.value(),
+                fired: Vec::new(),
+                variables: BTreeMap::new(),
+            },
+            history: Vec::new(),
+            entropy,
+            rng: Rng::new(seed),
+        }
+    }
+
+    /// Use `seed` for the run's random numbers instead of `Origin.seed`
+    ///
+    /// Must be called before the first tick to reproduce a run.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = Rng::new(seed);
+        self
+    }
+
+    /// Skip an event once it has waited `ticks` ticks for its `requires`
+    /// instead of [`DEFAULT_PATIENCE`]
+    ///
+    /// With a patience of 0, an event whose `requires` does not hold when it
+    /// is due is skipped at once.
+    pub fn with_patience(mut self, ticks: u64) -> Self {
+        self.patience = ticks;
+        self
+    }
+
+    /// The seed of the run's random numbers
+    pub fn seed(&self) -> u64 {
+        self.rng.seed()
+    }
+
+    /// The random stream called `name`
+    ///
+    /// Each event's `chance` is drawn from its own stream, `"chance:"`
+    /// followed by the event, so that one drawing more numbers does not
+    /// change what another sees.
+    pub fn stream(&mut self, name: &str) -> &mut Stream {
+        self.rng.stream(name)
+    }
+
+    /// The timeline being simulated, if any
+    pub fn timeline(&self) -> Option<&CitizenRef> {
+        self.timeline.as_ref()
+    }
+
+    /// Events in the order they are due
+    pub fn schedule(&self) -> &[CitizenRef] {
+        &self.schedule
+    }
+
+    /// The typed view of the world being run
+    pub fn typed(&self) -> &TypedWorld {
+        &self.typed
+    }
+
+    /// The current state
+    pub fn state(&self) -> &WorldState {
+        &self.state
+    }
+
+    /// World entropy and its history by tick
+    pub fn entropy(&self) -> &EntropyModel {
+        &self.entropy
+    }
+
+    /// The current value of a numeric world metric
+    ///
+    /// `entropy` is world entropy; any other name is looked up among the
+    /// variables set by event effects.
+    pub fn metric(&self, name: &str) -> Option<f64> {
+        match name {
+            "entropy" => Some(self.state.entropy),
+            _ => self.state.variables.get(name).and_then(Value::as_number),
+        }
+    }
+
+    /// Every tick run so far
+    pub fn history(&self) -> &[TickRecord] {
+        &self.history
+    }
+
+    /// Whether every scheduled event has fired, been refused or been skipped
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.schedule.len()
+    }
+
+    /// Run `ticks` more ticks
+    pub fn run(&mut self, ticks: u64) -> &[TickRecord] {
+        let start = self.history.len();
+        for _ in 0..ticks {
+            self.step();
+        }
+        &self.history[start..]
+    }
+
+    /// Run one tick
+    pub fn step(&mut self) -> &TickRecord {
+        let position = self.next;
+        self.state.tick += 1;
+        let (era, rules) = match self.era_at(position) {
+            Some((interval, rules)) => (Some(interval.era.name.clone()), Some(rules.clone())),
+            None => (None, None),
+        };
+        let event = self.schedule.get(position).cloned();
+        self.entropy.begin_tick();
+        self.state.entropy = self.entropy.value();
+
+        // Unresolved events are reported by validation
+        let typed_event = event.as_ref().and_then(|event| Some((event, self.typed.event(event)?)));
+        let outcome = match typed_event {
+            None => Outcome::Idle,
+            Some((event, typed_event)) => {
+                let (requires, effects) = self.compiled.get(event).cloned().unwrap_or_default();
+                let env = StateEnv {
+                    state: &self.state,
+                    era: era.as_deref(),
+                    variables: &self.variables,
+                };
+                let ready = requires.is_none_or(|requires| eval(&requires, &env) == Some(ExprValue::Boolean(true)));
+                if !ready {
+                    if self.waited >= self.patience {
+                        Outcome::Skipped
+                    } else {
+                        Outcome::Waiting
+                    }
+                } else if typed_event
+                    .chance
+                    .is_some_and(|chance| !self.rng.stream(&chance_stream(event)).chance(chance))
+                {
+                    Outcome::Missed
+                } else {
+                    match self.entropy.propose(typed_event.entropy_change) {
+                        None => Outcome::OutOfBounds,
+                        Some(entropy) => {
+                            let breaches = rules
+                                .map(|rules| rules.check(typed_event, entropy))
+                                .unwrap_or_default();
+                            // Every effect sees the state before the event
+                            let updates: Option<Vec<(String, Option<Value>)>> = effects
+                                .iter()
+                                .map(|(key, effect)| Some((key.clone(), apply_effect(&env, key, effect)?)))
+                                .collect();
+                            if !breaches.is_empty() {
+                                Outcome::Refused(breaches)
+                            } else if let Some(updates) = updates {
+                                for (key, value) in updates {
+                                    match value {
+                                        Some(value) => self.state.variables.insert(key, value),
+                                        None => self.state.variables.remove(&key),
+                                    };
+                                }
+                                self.entropy.apply(typed_event.entropy_change);
+                                self.state.fired.push(event.clone());
+                                Outcome::Fired
+                            } else {
+                                // An effect that cannot be evaluated leaves the state as it was
+                                Outcome::Skipped
+                            }
+                        }
+                    }
+                }
+            }
+        };
+        if outcome == Outcome::Waiting {
+            self.waited += 1;
+        } else if event.is_some() {
+            self.next += 1;
+            self.waited = 0;
+        }
+        self.entropy.end_tick();
+        self.state.entropy = self.entropy.value();
+
+        self.state.era = era.clone();
+        self.history.push(TickRecord {
+            tick: self.state.tick,
+            era,
+            event,
+            outcome,
+            entropy: self.state.entropy,
+        });
+        self.history.last().expect("a record was just pushed")
+    }
+
+    /// The era covering the event at `position` of the schedule
+    ///
+    /// Past the end of the schedule, an era that runs to the end of the
+    /// history stays active.
+    fn era_at(&self, position: usize) -> Option<&(EraInterval, EraRules)> {
+        if position < self.schedule.len() {
+            self.eras.iter().find(|(interval, _)| interval.contains(position))
+        } else {
+            self.eras
+                .iter()
+                .find(|(interval, _)| interval.end == self.schedule.len() && interval.start < interval.end)
+        }
+    }
+}
+
+/// The random stream deciding whether `event` happens
+fn chance_stream(event: &CitizenRef) -> String {
+    format!("chance:{}", event)
+}
+
+/// Every event of `world` in causal order, for worlds without a history
+///
+/// Each era's start comes before its end. Wherever that and causality leave
+/// a choice, core anchors and era starts come first, then the event declared
+/// first. A world whose causes form a cycle runs in declaration order.
+fn causal_schedule(world: &World, references: &ReferenceGraph) -> Vec<CitizenRef> {
+    let causal = CausalGraph::from_references(world, references);
+    let mut first: Vec<CitizenRef> = Vec::new();
+    if let Some(origin) = &world.origin {
+        let origin = CitizenRef::of(origin);
+        first.extend(references.targets(&origin, "core_anchors").cloned());
+    }
+    let mut constraints = Vec::new();
+    for era in world.eras.values() {
+        let era = CitizenRef::of(era);
+        let start = references.targets(&era, "start").next();
+        let end = references.targets(&era, "end").next();
+        if let (Some(start), Some(end)) = (start, end) {
+            constraints.push((start.clone(), end.clone()));
+        }
+        first.extend(start.cloned());
+    }
+    match causal.constrained_order(&constraints, |event| first.contains(event)) {
+        Some(order) => order.into_iter().cloned().collect(),
+        // Cycles are reported by validation
+        None => causal.events().to_vec(),
+    }
+}
+
+/// The new value of `key` after `effect`, `Some(None)` for no value
+///
+/// Returns `None` if the effect's expression cannot be evaluated.
+fn apply_effect(env: &StateEnv, key: &str, effect: &Effect) -> Option<Option<Value>> {
+    let value = match effect {
+        Effect::Add(delta) => match env.variable(key) {
+            Some(ExprValue::Number(current)) => Some(Value::Number(current + delta)),
+            _ => Some(Value::Number(*delta)),
+        },
+        Effect::Assign(expr) => eval(expr, env)?.to_value(),
+        Effect::Set(value) => Some(value.clone()),
+    };
+    Some(value)
+}
+
+/// The state as seen by `requires` and `effects` expressions
+struct StateEnv<'a> {
+    state: &'a WorldState,
+    era: Option<&'a str>,
+    variables: &'a Variables,
+}
+
+impl Env for StateEnv<'_> {
+    fn variable(&self, name: &str) -> Option<ExprValue> {
+        match name {
+            "entropy" => Some(ExprValue::Number(self.state.entropy)),
+            "tick" => Some(ExprValue::Number(self.state.tick as f64)),
+            "era" => Some(ExprValue::Symbol(self.era.map(str::to_string))),
+            _ => self
+                .state
+                .variables
+                .get(name)
+                .and_then(ExprValue::from_value)
+                .or_else(|| self.variables.initial(name)),
+        }
+    }
+
+    fn happened(&self, event: &CitizenRef) -> bool {
+        self.state.fired.iter().any(|fired| fired == event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::CLDParser;
+
+    fn world(source: &str) -> World {
+        let document = CLDParser::parse_document(source).expect("test source parses");
+        let (world, errors) = World::build_document(document);
+        assert!(errors.is_empty(), "{:?}", errors);
+        world
+    }
+
+    #[test]
+    fn events_and_core_events_sharing_a_name_fire_separately() {
+        let world = world(
+            r#"
+            @CoreEvent[war] { effects: { dead: 10 } }
+            @Event[war] { effects: { peace: 1 } }
+            "#,
+        );
+        let mut simulation = Simulation::new(&world);
+        simulation.run(2);
+
+        let kinds: Vec<&str> = simulation
+            .history()
+            .iter()
+            .filter_map(|record| record.event.as_ref())
+            .map(|event| event.kind.as_str())
+            .collect();
+        assert_eq!(kinds, ["CoreEvent", "Event"]);
+        assert_eq!(simulation.metric("dead"), Some(10.0));
+        assert_eq!(simulation.metric("peace"), Some(1.0));
+    }
+
+    #[test]
+    fn happened_tells_events_and_core_events_apart() {
+        let world = world(
+            r#"
+            @CoreEvent[war] { effects: { dead: 10 } }
+            @Event[war] { effects: { peace: 1 } }
+            "#,
+        );
+        let mut simulation = Simulation::new(&world);
+        simulation.step();
+
+        let (variables, _) = Variables::infer(&world);
+        let env = StateEnv {
+            state: simulation.state(),
+            era: None,
+            variables: &variables,
+        };
+        let war = |kind: &str| CitizenRef {
+            kind: kind.to_string(),
+            name: "war".to_string(),
+        };
+        assert!(env.happened(&war("CoreEvent")));
+        assert!(!env.happened(&war("Event")));
+    }
+
+    fn schedule_names(simulation: &Simulation) -> Vec<&str> {
+        simulation.schedule().iter().map(|event| event.name.as_str()).collect()
+    }
+
+    #[test]
+    fn fallback_schedule_puts_era_starts_before_their_ends() {
+        let world = world(
+            r#"
+            @Event[birth_of_stars] { }
+            @CoreEvent[big_bang] { }
+            @Era[stellar_era] { start: big_bang end: birth_of_stars }
+            "#,
+        );
+        assert_eq!(schedule_names(&Simulation::new(&world)), ["big_bang", "birth_of_stars"]);
+    }
+
+    #[test]
+    fn fallback_schedule_prefers_core_anchors_within_causal_order() {
+        let world = world(
+            r#"
+            @Origin[o] { core_anchors: [big_bang] }
+            @Event[drift] { }
+            @Event[stars] { caused_by: [dust] }
+            @Event[dust] { }
+            @CoreEvent[big_bang] { causes: [stars] }
+            "#,
+        );
+        assert_eq!(
+            schedule_names(&Simulation::new(&world)),
+            ["big_bang", "drift", "dust", "stars"]
+        );
+    }
+
+    #[test]
+    fn events_that_never_become_ready_are_skipped() {
+        let world = world(
+            r#"
+            @CoreEvent[big_bang] { requires: "tick > 100" }
+            @Event[stars] { caused_by: [big_bang] effects: { n: 1 } }
+            "#,
+        );
+        let mut simulation = Simulation::new(&world).with_patience(2);
+        let outcomes: Vec<Outcome> = simulation.run(4).iter().map(|record| record.outcome.clone()).collect();
+        assert_eq!(
+            outcomes,
+            [Outcome::Waiting, Outcome::Waiting, Outcome::Skipped, Outcome::Fired]
+        );
+        assert!(simulation.is_finished());
+        assert_eq!(simulation.metric("n"), Some(1.0));
+    }
+
+    #[test]
+    fn events_that_divide_by_zero_do_not_fire() {
+        let world = world(
+            r#"
+            @CoreEvent[big_bang] { effects: { stars: 10, zero: 0 } }
+            @Event[collapse] { caused_by: [big_bang] effects: { stars: "stars / zero", dust: 1 } }
+            @Event[stall] { caused_by: [collapse] requires: "stars / zero > 1" }
+            "#,
+        );
+        let mut simulation = Simulation::new(&world).with_patience(1);
+        let outcomes: Vec<Outcome> = simulation.run(4).iter().map(|record| record.outcome.clone()).collect();
+        assert_eq!(
+            outcomes,
+            [Outcome::Fired, Outcome::Skipped, Outcome::Waiting, Outcome::Skipped]
+        );
+        // The skipped event leaves every variable as it was
+        assert_eq!(simulation.metric("stars"), Some(10.0));
+        assert_eq!(simulation.metric("dust"), None);
+    }
+
+    #[test]
+    fn waiting_counts_restart_for_each_event() {
+        let world = world(
+            r#"
+            @Event[a] { requires: "tick >= 2" }
+            @Event[b] { caused_by: [a] requires: "tick >= 4" }
+            "#,
+        );
+        let mut simulation = Simulation::new(&world).with_patience(1);
+        let outcomes: Vec<Outcome> = simulation.run(4).iter().map(|record| record.outcome.clone()).collect();
+        assert_eq!(outcomes, [Outcome::Waiting, Outcome::Fired, Outcome::Waiting, Outcome::Fired]);
+    }
+
+    fn outcomes(world: &World, seed: u64) -> Vec<Outcome> {
+        let mut simulation = Simulation::new(world).with_seed(seed);
+        simulation.run(16).iter().map(|record| record.outcome.clone()).collect()
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_run() {
+        let source: String = (0..16)
+            .map(|i| format!("@Event[e{}] {{ chance: 0.5 effects: {{ n: 1 }} }}\n", i))
+            .collect();
+        let world = world(&source);
+        let first = outcomes(&world, 7);
+        assert_eq!(first, outcomes(&world, 7));
+        assert!(first.contains(&Outcome::Fired) && first.contains(&Outcome::Missed));
+        assert!((0..8).any(|seed| outcomes(&world, seed) != first));
+    }
+
+    #[test]
+    fn certain_chances_do_not_depend_on_the_seed() {
+        let world = world(
+            r#"
+            @Event[never] { chance: 0 }
+            @Event[always] { caused_by: [never] chance: 1 }
+            "#,
+        );
+        for seed in 0..8 {
+            assert_eq!(outcomes(&world, seed)[..2], [Outcome::Missed, Outcome::Fired]);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Seeded, reproducible randomness
+//!
+//! Every random draw of a run comes from one [`Rng`], seeded from the
+//! command line or `Origin.seed`. Draws never share a sequence: each event's
+//! `chance` is decided by its own named [`Stream`] (e.g.
+//! `"chance:Event[first_light]"`), whose state depends only on the seed and
+//! the name. Adding an event, or drawing more from one stream, leaves every
+//! other stream's sequence unchanged, so a run is reproduced exactly from
+//! the world file and the seed.
+//!
+//! Streams are xoshiro256** generators whose state is expanded from
+//! `seed ^ fnv1a(name)` with SplitMix64.
+
+use std::collections::BTreeMap;
+
+/// The random number service of one run
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rng {
+    seed: u64,
+    streams: BTreeMap<String, Stream>,
+}
+
+impl Rng {
+    /// A run's randomness, with every stream derived from `seed`
+    pub fn new(seed: u64) -> Self {
+        Rng {
+            seed,
+            streams: BTreeMap::new(),
+        }
+    }
+
+    /// The seed the run was started with
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The stream called `name`, created on first use
+    pub fn stream(&mut self, name: &str) -> &mut Stream {
+        let seed = self.seed;
+        self.streams
+            .entry(name.to_string())
+            .or_insert_with(|| Stream::new(seed, name))
+    }
+
+    /// Names of the streams drawn from so far, in sorted order
+    pub fn stream_names(&self) -> impl Iterator<Item = &str> {
+        self.streams.keys().map(String::as_str)
+    }
+}
+
+/// One independent sequence of random numbers
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stream {
+    state: [u64; 4],
+}
+
+impl Stream {
+    /// The stream called `name` under `seed`
+    pub fn new(seed: u64, name: &str) -> Self {
+        let mut splitmix = seed ^ fnv1a(name.as_bytes());
+        let mut state = [0; 4];
+        for word in &mut state {
+            *word = splitmix64(&mut splitmix);
+        }
+        // xoshiro must not start from the all-zero state
+        if state == [0; 4] {
+            state[0] = 1;
+        }
+        Stream { state }
+    }
+
+    /// The next 64 random bits (xoshiro256**)
+    pub fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+
+    /// A number in `0.0..1.0`
+    pub fn next_f64(&mut self) -> f64 {
+        // The top 53 bits fill an f64 mantissa exactly
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A number in `0..bound`, without modulo bias
+    ///
+    /// Returns 0 if `bound` is 0.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        if bound == 0 {
+            return 0;
+        }
+        // Reject draws from the incomplete top block of the u64 range
+        let zone = u64::MAX - u64::MAX % bound;
+        loop {
+            let draw = self.next_u64();
+            if draw < zone {
+                return draw % bound;
+            }
+        }
+    }
+
+    /// True with probability `p`
+    pub fn chance(&mut self, p: f64) -> bool {
+        self.next_f64() < p
+    }
+
+    /// A uniformly chosen element of `items`
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        if items.is_empty() {
+            return None;
+        }
+        items.get(self.below(items.len() as u64) as usize)
+    }
+}
+
+/// 64-bit FNV-1a hash
+fn fnv1a(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    bytes
+        .iter()
+        .fold(OFFSET_BASIS, |hash, &byte| (hash ^ byte as u64).wrapping_mul(PRIME))
+}
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draws(stream: &mut Stream, count: usize) -> Vec<u64> {
+        (0..count).map(|_| stream.next_u64()).collect()
+    }
+
+    #[test]
+    fn same_seed_and_name_give_the_same_sequence() {
+        let mut first = Rng::new(42);
+        let mut second = Rng::new(42);
+        assert_eq!(draws(first.stream("chance"), 8), draws(second.stream("chance"), 8));
+        assert_ne!(draws(first.stream("chance"), 8), draws(Rng::new(43).stream("chance"), 8));
+    }
+
+    #[test]
+    fn new_streams_leave_existing_streams_unchanged() {
+        let mut alone = Rng::new(7);
+        let expected = draws(alone.stream("a"), 8);
+
+        let mut shared = Rng::new(7);
+        let mut drawn = draws(shared.stream("a"), 4);
+        draws(shared.stream("b"), 16);
+        drawn.extend(draws(shared.stream("a"), 4));
+        assert_eq!(drawn, expected);
+        assert_eq!(shared.stream_names().collect::<Vec<_>>(), ["a", "b"]);
+    }
+
+    #[test]
+    fn below_and_pick_stay_in_range() {
+        let mut stream = Stream::new(1, "range");
+        assert_eq!(stream.below(0), 0);
+        assert!((0..100).all(|_| stream.below(3) < 3));
+        assert_eq!(stream.pick::<u8>(&[]), None);
+        assert!(stream.pick(&[1, 2, 3]).is_some());
+    }
+}
@@ -0,0 +1,487 @@
+//! The CLD v6 schema
+//!
+//! Well-known fields have a canonical id and are accepted under both an
+//! English and a Chinese alias. The parser stores every field under its
+//! canonical id, so consumers only ever look up the canonical name.
+//!
+//! A [`Schema`] declares which fields each citizen kind has and of what
+//! type. The built-in CLD v6 schema can be extended from CLD files:
+//!
+//! ```text
+//! @Schema[Event] {
+//!     entropy_change: number(-1..1) required
+//!     tags: list(identifier)
+//! }
+//! ```
+//!
+//! Types are `any`, `string`, `text` (string or identifier), `number`,
+//! `number(min..max)` (either bound may be omitted), `boolean`, `identifier`,
+//! `ref(Kind | ...)`, `kind`, `list(type)`, `map` and `emotion`.
+
+use crate::citizens::{CitizenData, Value};
+use crate::error::CldError;
+use crate::span::{Span, Spanned};
+use crate::world::World;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A well-known field and the names it may be written as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldAlias {
+    /// Canonical id used as the field key after parsing
+    pub id: &'static str,
+    /// English spelling
+    pub en: &'static str,
+    /// Chinese spelling
+    pub zh: &'static str,
+}
+
+/// Fields defined by the CLD v6 specification
+pub const WELL_KNOWN_FIELDS: &[FieldAlias] = &[
+    FieldAlias { id: "core_anchors", en: "core_anchors", zh: "核心锚点" },
+    FieldAlias { id: "entropy", en: "entropy", zh: "熵" },
+    FieldAlias { id: "seed", en: "seed", zh: "种子" },
+    FieldAlias { id: "entropy_change", en: "entropy_change", zh: "熵变" },
+    FieldAlias { id: "entropy_decay", en: "entropy_decay", zh: "熵衰减" },
+    FieldAlias { id: "entropy_clamp", en: "entropy_clamp", zh: "熵钳制" },
+    FieldAlias { id: "emotion_peak", en: "emotion_peak", zh: "情感峰值" },
+    FieldAlias { id: "emotions", en: "emotions", zh: "情感" },
+    FieldAlias { id: "description", en: "description", zh: "描述" },
+    FieldAlias { id: "requires", en: "requires", zh: "条件" },
+    FieldAlias { id: "chance", en: "chance", zh: "概率" },
+    FieldAlias { id: "causes", en: "causes", zh: "导致" },
+    FieldAlias { id: "caused_by", en: "caused_by", zh: "起因" },
+    FieldAlias { id: "origin", en: "origin", zh: "起源" },
+    FieldAlias { id: "parent", en: "parent", zh: "父时间线" },
+    FieldAlias { id: "fork", en: "fork", zh: "分叉点" },
+    FieldAlias { id: "events", en: "events", zh: "事件" },
+    FieldAlias { id: "start", en: "start", zh: "开始" },
+    FieldAlias { id: "end", en: "end", zh: "结束" },
+    FieldAlias { id: "timeline", en: "timeline", zh: "时间线" },
+    FieldAlias { id: "rules", en: "rules", zh: "规则" },
+    FieldAlias { id: "tags", en: "tags", zh: "标签" },
+    FieldAlias { id: "capabilities", en: "capabilities", zh: "能力" },
+    FieldAlias { id: "allowed_tags", en: "allowed_tags", zh: "允许标签" },
+    FieldAlias { id: "forbidden_tags", en: "forbidden_tags", zh: "禁止标签" },
+    FieldAlias { id: "min_entropy", en: "min_entropy", zh: "最小熵" },
+    FieldAlias { id: "max_entropy", en: "max_entropy", zh: "最大熵" },
+    FieldAlias { id: "strategy", en: "strategy", zh: "策略" },
+    FieldAlias { id: "stress_response", en: "stress_response", zh: "应激反应" },
+    FieldAlias { id: "source", en: "source", zh: "来源" },
+    FieldAlias { id: "output_type", en: "output_type", zh: "输出类型" },
+    FieldAlias { id: "storage_model", en: "storage_model", zh: "存储模型" },
+    FieldAlias { id: "retention_policy", en: "retention_policy", zh: "保留策略" },
+    FieldAlias { id: "monitor_metric", en: "monitor_metric", zh: "监控指标" },
+    FieldAlias { id: "threshold", en: "threshold", zh: "阈值" },
+    FieldAlias { id: "action", en: "action", zh: "动作" },
+];
+
+/// Look up a well-known field by its canonical id or either alias
+pub fn lookup_field(key: &str) -> Option<&'static FieldAlias> {
+    WELL_KNOWN_FIELDS
+        .iter()
+        .find(|field| field.id == key || field.en == key || field.zh == key)
+}
+
+/// Map a field key as written to its canonical id
+///
+/// Keys that are not well-known are returned unchanged.
+pub fn canonical_field_name(key: &str) -> &str {
+    lookup_field(key).map(|field| field.id).unwrap_or(key)
+}
+
+/// The type a field's value must have
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FieldType {
+    /// Any value
+    Any,
+    /// A quoted string
+    String,
+    /// Free text: a string or a bare identifier
+    Text,
+    /// A number, optionally within inclusive bounds
+    Number { min: Option<f64>, max: Option<f64> },
+    Boolean,
+    /// A bare identifier with no further meaning
+    Identifier,
+    /// An identifier naming a citizen of one of the given kinds
+    Ref { kinds: Vec<String> },
+    /// An identifier naming a citizen kind, e.g. `Event`
+    Kind,
+    List { item: Box<FieldType> },
+    Map,
+    /// An emotion label, or a map with a `label` and optional `intensity`
+    Emotion,
+}
+
+impl FieldType {
+    /// Check `value` against this type
+    ///
+    /// Returns the first offending value (which may be nested in a list)
+    /// together with the type it should have had.
+    pub fn check<'a>(&'a self, value: &'a Spanned<Value>) -> Result<(), (&'a Spanned<Value>, &'a FieldType)> {
+        let matches = match (self, &value.node) {
+            (FieldType::Any, _) => true,
+            (FieldType::String, Value::String(_)) => true,
+            (FieldType::Text, Value::String(_) | Value::Identifier(_)) => true,
+            (FieldType::Number { .. }, Value::Number(_)) => true,
+            (FieldType::Boolean, Value::Boolean(_)) => true,
+            (FieldType::Identifier | FieldType::Ref { .. } | FieldType::Kind, Value::Identifier(_)) => true,
+            (FieldType::List { item }, Value::List(items)) => {
+                return items.iter().try_for_each(|element| item.check(element));
+            }
+            (FieldType::Map, Value::Map(_)) => true,
+            (FieldType::Emotion, Value::String(_) | Value::Identifier(_)) => true,
+            (FieldType::Emotion, Value::Map(map)) => {
+                matches!(map.get("label"), Some(Value::String(_) | Value::Identifier(_)))
+                    && matches!(map.get("intensity"), None | Some(Value::Number(_)))
+            }
+            _ => false,
+        };
+        if matches {
+            Ok(())
+        } else {
+            Err((value, self))
+        }
+    }
+}
+
+impl std::fmt::Display for FieldType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FieldType::Any => write!(f, "any value"),
+            FieldType::String => write!(f, "a string"),
+            FieldType::Text => write!(f, "a string or identifier"),
+            FieldType::Number { min, max } => {
+                write!(f, "a number")?;
+                match (min, max) {
+                    (None, None) => Ok(()),
+                    (Some(min), Some(max)) => write!(f, " in {}..{}", min, max),
+                    (Some(min), None) => write!(f, " of at least {}", min),
+                    (None, Some(max)) => write!(f, " of at most {}", max),
+                }
+            }
+            FieldType::Boolean => write!(f, "a boolean"),
+            FieldType::Identifier => write!(f, "an identifier"),
+            FieldType::Ref { kinds } => write!(f, "a reference to {}", kinds.join(" or ")),
+            FieldType::Kind => write!(f, "a citizen kind"),
+            FieldType::List { item } => write!(f, "a list of ({})", item),
+            FieldType::Map => write!(f, "a map"),
+            FieldType::Emotion => write!(f, "an emotion label or {{ label, intensity }} map"),
+        }
+    }
+}
+
+/// The declared type of one field of a citizen kind
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldSchema {
+    /// Canonical field id
+    pub name: String,
+    pub ty: FieldType,
+    pub required: bool,
+    /// Where the field was declared, for custom schemas
+    #[serde(default)]
+    pub span: Option<Span>,
+}
+
+/// A parsed `@Schema[Kind] { ... }` declaration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SchemaDecl {
+    pub kind: String,
+    #[serde(default)]
+    pub kind_span: Span,
+    pub fields: Vec<FieldSchema>,
+    #[serde(default)]
+    pub span: Span,
+}
+
+/// The citizen kinds of CLD v6, as written after `@` (or `>>`)
+pub const CITIZEN_KINDS: &[&str] = &[
+    "Origin", "Timeline", "Event", "CoreEvent", "Niche", "Era", "Generator", "Memory", "Immune",
+];
+
+/// Field declarations for every citizen kind
+///
+/// Fields not listed for a kind are allowed but reported as warnings, since
+/// they are usually typos; they are kept as extensions when lowering.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Schema {
+    kinds: BTreeMap<String, Vec<FieldSchema>>,
+}
+
+impl Default for Schema {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl Schema {
+    /// The built-in CLD v6 schema
+    pub fn builtin() -> Self {
+        use FieldType::*;
+
+        let number = |min, max| Number { min, max };
+        let refs = |kinds: &[&str]| Ref {
+            kinds: kinds.iter().map(|kind| kind.to_string()).collect(),
+        };
+        let event_fields = vec![
+            field("entropy_change", number(Some(-1.0), Some(1.0)), false),
+            field("emotion_peak", Emotion, false),
+            field("requires", String, false),
+            field("chance", number(Some(0.0), Some(1.0)), false),
+            field("effects", Map, false),
+            field("causes", List { item: Box::new(refs(&["Event", "CoreEvent"])) }, false),
+            field("caused_by", List { item: Box::new(refs(&["Event", "CoreEvent"])) }, false),
+            field("tags", List { item: Box::new(Identifier) }, false),
+            field("capabilities", List { item: Box::new(Identifier) }, false),
+            field("description", Text, false),
+        ];
+
+        let mut kinds = BTreeMap::new();
+        kinds.insert(
+            "Origin".to_string(),
+            vec![
+                field("entropy", number(Some(0.0), Some(1.0)), false),
+                field("min_entropy", number(Some(0.0), Some(1.0)), false),
+                field("max_entropy", number(Some(0.0), Some(1.0)), false),
+                field("entropy_decay", number(Some(0.0), Some(1.0)), false),
+                field("entropy_clamp", Text, false),
+                field("seed", number(Some(0.0), None), false),
+                field("emotions", Map, false),
+                field("core_anchors", List { item: Box::new(refs(&["CoreEvent"])) }, false),
+                field("description", Text, false),
+            ],
+        );
+        kinds.insert(
+            "Timeline".to_string(),
+            vec![
+                field("origin", refs(&["Origin"]), false),
+                field("parent", refs(&["Timeline"]), false),
+                field("fork", refs(&["Event", "CoreEvent"]), false),
+                field("events", List { item: Box::new(refs(&["Event", "CoreEvent"])) }, false),
+                field("description", Text, false),
+            ],
+        );
+        kinds.insert("Event".to_string(), event_fields.clone());
+        kinds.insert("CoreEvent".to_string(), event_fields);
+        kinds.insert(
+            "Niche".to_string(),
+            vec![
+                field("strategy", Text, false),
+                field("stress_response", Text, false),
+                field("description", Text, false),
+            ],
+        );
+        kinds.insert(
+            "Era".to_string(),
+            vec![
+                field("start", refs(&["Event", "CoreEvent"]), true),
+                field("end", refs(&["Event", "CoreEvent"]), false),
+                field("timeline", refs(&["Timeline"]), false),
+                field("rules", Text, false),
+                field("allowed_tags", List { item: Box::new(Identifier) }, false),
+                field("forbidden_tags", List { item: Box::new(Identifier) }, false),
+                field("capabilities", List { item: Box::new(Identifier) }, false),
+                field("min_entropy", number(Some(0.0), Some(1.0)), false),
+                field("max_entropy", number(Some(0.0), Some(1.0)), false),
+                field("description", Text, false),
+            ],
+        );
+        kinds.insert(
+            "Generator".to_string(),
+            vec![
+                field("source", Text, false),
+                field("output_type", Kind, true),
+                field("description", Text, false),
+            ],
+        );
+        kinds.insert(
+            "Memory".to_string(),
+            vec![
+                field("storage_model", Text, false),
+                field("retention_policy", Text, false),
+                field("description", Text, false),
+            ],
+        );
+        kinds.insert(
+            "Immune".to_string(),
+            vec![
+                field("monitor_metric", Text, true),
+                field("threshold", number(None, None), true),
+                field("action", Text, false),
+                field("description", Text, false),
+            ],
+        );
+        Schema { kinds }
+    }
+
+    /// The declared fields of a citizen kind
+    pub fn fields(&self, kind: &str) -> &[FieldSchema] {
+        self.kinds.get(kind).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// The declaration of one field of a citizen kind
+    pub fn field(&self, kind: &str, name: &str) -> Option<&FieldSchema> {
+        self.fields(kind).iter().find(|field| field.name == name)
+    }
+
+    /// Apply a custom `@Schema` declaration
+    ///
+    /// Declared fields replace built-in fields of the same name and are
+    /// otherwise added to the kind.
+    pub fn extend(&mut self, decl: &SchemaDecl) -> Result<(), CldError> {
+        if !CITIZEN_KINDS.contains(&decl.kind.as_str()) {
+            return Err(CldError::rule(
+                "!Schema",
+                format!(
+                    "'{}' is not a citizen kind (expected one of {})",
+                    decl.kind,
+                    CITIZEN_KINDS.join(", ")
+                ),
+                Some(decl.kind_span),
+            ));
+        }
+        let fields = self.kinds.entry(decl.kind.clone()).or_default();
+        for declared in &decl.fields {
+            match fields.iter_mut().find(|field| field.name == declared.name) {
+                Some(field) => *field = declared.clone(),
+                None => fields.push(declared.clone()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Check every citizen of `world` against the schema
+    ///
+    /// Reports missing required fields, values of the wrong type, numbers
+    /// outside their declared range, and (as warnings) undeclared fields.
+    pub fn check(&self, world: &World) -> Vec<CldError> {
+        let mut errors = Vec::new();
+        for citizen in world.citizens() {
+            self.check_citizen(citizen, &mut errors);
+        }
+        errors
+    }
+
+    fn check_citizen(&self, citizen: &dyn CitizenData, errors: &mut Vec<CldError>) {
+        let qualified = |key: &str| format!("{}[{}].{}", citizen.kind(), citizen.name(), key);
+
+        for declared in self.fields(citizen.kind()) {
+            if declared.required && !citizen.fields().contains_key(&declared.name) {
+                errors.push(CldError::MissingField {
+                    field: qualified(&declared.name),
+                    expected: declared.ty.to_string(),
+                    span: citizen.name_span(),
+                });
+            }
+        }
+
+        for field in citizen.fields() {
+            let Some(declared) = self.field(citizen.kind(), &field.key) else {
+                errors.push(CldError::UnknownField {
+                    field: qualified(field.written_key()),
+                    span: field.key_span,
+                });
+                continue;
+            };
+            check_value(&declared.ty, &field.value, &qualified(&field.key), errors);
+        }
+    }
+}
+
+/// Report values of the wrong type and numbers outside the bounds of `ty`
+///
+/// Each element of a list is checked on its own, so an element of the wrong
+/// type does not hide problems with the others.
+fn check_value(ty: &FieldType, value: &Spanned<Value>, field: &str, errors: &mut Vec<CldError>) {
+    if let (FieldType::List { item }, Value::List(items)) = (ty, &value.node) {
+        for element in items {
+            check_value(item, element, field, errors);
+        }
+        return;
+    }
+    match ty.check(value) {
+        Err((value, expected)) => errors.push(CldError::TypeMismatch {
+            field: field.to_string(),
+            expected: expected.to_string(),
+            found: value.described(),
+            span: value.span,
+        }),
+        Ok(()) => check_range(ty, value, field, errors),
+    }
+}
+
+/// Report a number outside the bounds of `ty`
+fn check_range(ty: &FieldType, value: &Spanned<Value>, field: &str, errors: &mut Vec<CldError>) {
+    if let (FieldType::Number { min, max }, Value::Number(number)) = (ty, &value.node) {
+        let below = min.is_some_and(|min| *number < min);
+        let above = max.is_some_and(|max| *number > max);
+        if below || above {
+            errors.push(CldError::OutOfRange {
+                field: field.to_string(),
+                value: *number,
+                min: *min,
+                max: *max,
+                span: value.span,
+            });
+        }
+    }
+}
+
+fn field(name: &str, ty: FieldType, required: bool) -> FieldSchema {
+    FieldSchema {
+        name: name.to_string(),
+        ty,
+        required,
+        span: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::CLDParser;
+
+    #[test]
+    fn list_elements_are_checked_one_by_one() {
+        let document = CLDParser::parse_document(
+            r#"
+            @Schema[Event] { weights: list(number(0..)) }
+            @Event[e] { weights: [1, -2, "x"] }
+            "#,
+        )
+        .expect("test source parses");
+        let (world, errors) = World::build_document(document);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let errors = world.schema.check(&world);
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert!(matches!(errors[0], CldError::OutOfRange { value, .. } if value == -2.0));
+        assert!(matches!(&errors[1], CldError::TypeMismatch { found, .. } if found == "a string"));
+    }
+
+    #[test]
+    fn mismatches_name_the_found_type_with_its_article() {
+        let document = CLDParser::parse_document(
+            r#"
+            @Schema[Event] { weight: number label: string }
+            @Event[e] { weight: heavy label: 3 }
+            "#,
+        )
+        .expect("test source parses");
+        let (world, errors) = World::build_document(document);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let found: Vec<String> = world
+            .schema
+            .check(&world)
+            .into_iter()
+            .filter_map(|error| match error {
+                CldError::TypeMismatch { found, .. } => Some(found),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(found, ["an identifier", "a number"]);
+    }
+}
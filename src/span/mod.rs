@@ -0,0 +1,151 @@
+//! Source positions for parsed CLD constructs
+//!
+//! Spans are recorded by the parser so that downstream tools can map every
+//! citizen back to its location in the original `.cld` file.
+
+use serde::{Deserialize, Serialize};
+
+/// A region of source text, with byte offsets and a 1-based start position
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    /// Index of the file in the [`SourceMap`]; 0 for the file that was
+    /// loaded first, and omitted from JSON in that case
+    #[serde(default, skip_serializing_if = "is_first_file")]
+    pub file: usize,
+    /// Byte offset of the first character
+    pub start: usize,
+    /// Byte offset one past the last character
+    pub end: usize,
+    /// 1-based line of `start`
+    pub line: u32,
+    /// 1-based column (in characters) of `start`
+    pub column: u32,
+}
+
+impl Span {
+    /// Build a span from a pest span
+    pub fn from_pest(span: pest::Span<'_>) -> Self {
+        let (line, column) = span.start_pos().line_col();
+        Span {
+            file: 0,
+            start: span.start(),
+            end: span.end(),
+            line: line as u32,
+            column: column as u32,
+        }
+    }
+}
+
+impl Span {
+    /// Span of the bytes `from..to` of `text`, where `text` is the source
+    /// covered by this span
+    pub fn sub(&self, text: &str, from: usize, to: usize) -> Span {
+        let before = &text[..from];
+        let (line, column) = match before.rfind('\n') {
+            Some(newline) => (
+                self.line + before.matches('\n').count() as u32,
+                before[newline + 1..].chars().count() as u32 + 1,
+            ),
+            None => (self.line, self.column + before.chars().count() as u32),
+        };
+        Span {
+            file: self.file,
+            start: self.start + from,
+            end: self.start + to,
+            line,
+            column,
+        }
+    }
+}
+
+fn is_first_file(file: &usize) -> bool {
+    *file == 0
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// A value paired with the span it was parsed from
+///
+/// Serializes as the inner value's own representation with an extra `span`
+/// entry, and dereferences to the inner value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Spanned<T> {
+    #[serde(flatten)]
+    pub node: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    /// Attach a span to a value
+    pub fn new(node: T, span: Span) -> Self {
+        Spanned { node, span }
+    }
+}
+
+impl<T> std::ops::Deref for Spanned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.node
+    }
+}
+
+impl<T: std::fmt::Display> std::fmt::Display for Spanned<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.node.fmt(f)
+    }
+}
+
+/// A loaded source file
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceFile {
+    /// The path as shown in diagnostics
+    pub name: String,
+    pub source: String,
+}
+
+/// The files of a multi-file world, indexed by [`Span::file`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    /// Create an empty source map
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file, returning its index
+    pub fn add(&mut self, name: impl Into<String>, source: impl Into<String>) -> usize {
+        self.files.push(SourceFile {
+            name: name.into(),
+            source: source.into(),
+        });
+        self.files.len() - 1
+    }
+
+    /// Get a file by index
+    pub fn get(&self, file: usize) -> Option<&SourceFile> {
+        self.files.get(file)
+    }
+
+    /// Names of all files, in index order
+    pub fn names(&self) -> Vec<String> {
+        self.files.iter().map(|file| file.name.clone()).collect()
+    }
+
+    /// Number of files
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Check whether no files have been added
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
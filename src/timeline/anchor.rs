@@ -0,0 +1,221 @@
+//! CoreEvent immutability
+//!
+//! CoreEvents are the fixed points of a world's history. Patches cannot
+//! change them (see [`crate::world::World::build`]), and this module checks
+//! the rest: a branch keeps every CoreEvent its parent reaches, generators
+//! do not produce CoreEvents, and no timeline places an event before a
+//! CoreEvent that causes it.
+
+use super::{listed_event_span, Branches, CausalGraph};
+use crate::diagnostics::{Label, Severity};
+use crate::error::CldError;
+use crate::resolve::{CitizenRef, ReferenceGraph};
+use crate::span::Span;
+use crate::world::World;
+
+/// Check that no timeline or generator rewrites a CoreEvent
+pub fn validate_anchors(
+    world: &World,
+    references: &ReferenceGraph,
+    causal: &CausalGraph,
+    branches: &Branches,
+) -> Vec<CldError> {
+    let mut errors = Vec::new();
+    validate_branches_keep_core_events(world, references, branches, &mut errors);
+    validate_generators(world, &mut errors);
+    validate_causal_precedence(world, references, causal, branches, &mut errors);
+    errors
+}
+
+/// A branch must reach every CoreEvent of its parent's history, including
+/// the ones after the fork
+///
+/// CoreEvents in `Origin.core_anchors` are left to
+/// [`super::validate_convergence`], which requires every timeline to reach
+/// them.
+fn validate_branches_keep_core_events(
+    world: &World,
+    references: &ReferenceGraph,
+    branches: &Branches,
+    errors: &mut Vec<CldError>,
+) {
+    let anchored: Vec<CitizenRef> = match &world.origin {
+        Some(origin) => references
+            .targets(&CitizenRef::of(origin), "core_anchors")
+            .cloned()
+            .collect(),
+        None => Vec::new(),
+    };
+    for branch in branches.iter() {
+        let Some(parent) = branch.parent.as_ref().and_then(|parent| branches.get(parent)) else {
+            continue;
+        };
+        for core_event in parent.sequence.iter().filter(|event| event.kind == "CoreEvent") {
+            if branch.sequence.contains(core_event) || anchored.contains(core_event) {
+                continue;
+            }
+            errors.push(violation(
+                format!(
+                    "Timeline '{}' drops CoreEvent '{}' reached by its parent '{}'",
+                    branch.timeline.name, core_event.name, parent.timeline.name
+                ),
+                world.name_span(&branch.timeline),
+                world.name_span(core_event).map(|span| Label {
+                    span,
+                    message: "CoreEvent declared here".to_string(),
+                    primary: false,
+                }),
+            ));
+        }
+    }
+}
+
+/// Generators may create events, but never CoreEvents
+fn validate_generators(world: &World, errors: &mut Vec<CldError>) {
+    for generator in world.generators.values() {
+        let Some(output_type) = generator.fields.field("output_type") else {
+            continue;
+        };
+        if output_type.value.as_identifier().map(String::as_str) == Some("CoreEvent") {
+            errors.push(violation(
+                format!("Generator '{}' cannot produce CoreEvents", generator.name),
+                Some(output_type.value.span),
+                None,
+            ));
+        }
+    }
+}
+
+/// No event may come before a CoreEvent that causes it, directly or
+/// indirectly, in any timeline's history
+fn validate_causal_precedence(
+    world: &World,
+    references: &ReferenceGraph,
+    causal: &CausalGraph,
+    branches: &Branches,
+    errors: &mut Vec<CldError>,
+) {
+    for branch in branches.iter() {
+        for (position, event) in branch.sequence.iter().enumerate() {
+            let later = &branch.sequence[position + 1..];
+            for core_event in causal.ancestors(event) {
+                if core_event.kind != "CoreEvent" || !later.contains(core_event) {
+                    continue;
+                }
+                let span = listed_event_span(references, &branch.timeline, event)
+                    .or_else(|| world.name_span(&branch.timeline));
+                errors.push(violation(
+                    format!(
+                        "Timeline '{}' places '{}' before CoreEvent '{}', which causes it",
+                        branch.timeline.name, event.name, core_event.name
+                    ),
+                    span,
+                    world.name_span(core_event).map(|span| Label {
+                        span,
+                        message: "CoreEvent declared here".to_string(),
+                        primary: false,
+                    }),
+                ));
+            }
+        }
+    }
+}
+
+fn violation(message: String, span: Option<Span>, related: Option<Label>) -> CldError {
+    CldError::RuleViolation {
+        rule: "!CoreEvent".to_string(),
+        message,
+        span,
+        severity: Severity::Error,
+        related: related.into_iter().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::CLDParser;
+    use crate::timeline::validate_convergence;
+
+    /// Every `!CoreEvent` and `!Convergence` message for `source`
+    fn violations(source: &str) -> Vec<String> {
+        let document = CLDParser::parse_document(source).expect("test source parses");
+        let (world, errors) = World::build_document(document);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let references = world.reference_graph();
+        let causal = CausalGraph::from_references(&world, &references);
+        let mut errors = Vec::new();
+        let branches = Branches::build(&world, &references, &mut errors);
+        assert!(errors.is_empty(), "{:?}", errors);
+        errors.extend(validate_convergence(&world, &references, &branches));
+        errors.extend(validate_anchors(&world, &references, &causal, &branches));
+        errors
+            .iter()
+            .map(|error| match error {
+                CldError::RuleViolation { rule, message, .. } => format!("{}: {}", rule, message),
+                other => other.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn branches_that_drop_a_parent_core_event_are_reported() {
+        let errors = violations(
+            r#"
+            @Origin[o] { }
+            @CoreEvent[dawn] { }
+            @Event[e1] { }
+            @Event[e2] { }
+            @Timeline[main] { events: [e1, dawn] }
+            @Timeline[alt] { parent: main fork: e1 events: [e2] }
+            @Timeline[kept] { parent: main fork: e1 events: [dawn] }
+            "#,
+        );
+        assert_eq!(errors, ["!CoreEvent: Timeline 'alt' drops CoreEvent 'dawn' reached by its parent 'main'"]);
+    }
+
+    #[test]
+    fn dropped_anchors_are_reported_once_by_convergence() {
+        let errors = violations(
+            r#"
+            @Origin[o] { core_anchors: [anchor] }
+            @CoreEvent[anchor] { }
+            @Event[e1] { }
+            @Event[e2] { }
+            @Timeline[main] { events: [e1, anchor] }
+            @Timeline[alt] { parent: main fork: e1 events: [e2] }
+            "#,
+        );
+        assert_eq!(errors, ["!Convergence: Timeline 'alt' never reaches the anchored CoreEvent 'anchor'"]);
+    }
+
+    #[test]
+    fn generators_cannot_produce_core_events() {
+        let errors = violations(
+            r#"
+            @Origin[o] { }
+            >>Generator[events] { output_type: Event }
+            >>Generator[anchors] { output_type: CoreEvent }
+            "#,
+        );
+        assert_eq!(errors, ["!CoreEvent: Generator 'anchors' cannot produce CoreEvents"]);
+    }
+
+    #[test]
+    fn events_cannot_come_before_the_core_events_that_cause_them() {
+        let errors = violations(
+            r#"
+            @Origin[o] { }
+            @CoreEvent[big_bang] { causes: [dust] }
+            @Event[dust] { causes: [stars] }
+            @Event[stars] { }
+            @Timeline[main] { events: [stars, big_bang, dust] }
+            @Timeline[ok] { events: [big_bang, dust, stars] }
+            "#,
+        );
+        assert_eq!(
+            errors,
+            ["!CoreEvent: Timeline 'main' places 'stars' before CoreEvent 'big_bang', which causes it"]
+        );
+    }
+}
@@ -0,0 +1,365 @@
+//! Branching timelines
+//!
+//! A `@Timeline` lists its events in order with `events: [...]`. A branch
+//! also names the timeline it splits from with `parent` and the event it
+//! splits at with `fork`; its history is the parent's history up to and
+//! including the fork, followed by its own events.
+
+use crate::error::CldError;
+use crate::resolve::{CitizenRef, ReferenceGraph};
+use crate::span::Span;
+use crate::world::World;
+use std::collections::HashMap;
+
+/// One timeline and its place in the branch tree
+#[derive(Debug, Clone, PartialEq)]
+pub struct Branch {
+    pub timeline: CitizenRef,
+    /// The timeline this one splits from; `None` for a main timeline
+    pub parent: Option<CitizenRef>,
+    /// The event of the parent's history after which this branch splits
+    pub fork: Option<CitizenRef>,
+    /// Events declared by the timeline itself, in order
+    pub events: Vec<CitizenRef>,
+    /// The full history: the shared prefix inherited from the parent
+    /// followed by `events`
+    pub sequence: Vec<CitizenRef>,
+}
+
+/// Whether one timeline reaches every required CoreEvent
+#[derive(Debug, Clone, PartialEq)]
+pub struct Convergence {
+    pub timeline: CitizenRef,
+    /// Required CoreEvents missing from the timeline's history
+    pub missing: Vec<CitizenRef>,
+}
+
+impl Convergence {
+    /// Whether the timeline reaches every required CoreEvent
+    pub fn converges(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+/// Every timeline of a world with its computed history
+#[derive(Debug, Clone, Default)]
+pub struct Branches {
+    branches: Vec<Branch>,
+    index: HashMap<CitizenRef, usize>,
+}
+
+impl Branches {
+    /// Compute the branches of `world`
+    ///
+    /// Problems with the branch structure are reported in `errors`: a fork
+    /// without a parent or the other way round, a fork that is not part of
+    /// the parent's history, and timelines that are their own ancestors.
+    /// The history of such a timeline only holds its own events.
+    pub fn build(world: &World, references: &ReferenceGraph, errors: &mut Vec<CldError>) -> Self {
+        let mut branches = Branches::default();
+        for citizen in world.declaration_order() {
+            if citizen.kind != "Timeline" {
+                continue;
+            }
+            let single = |field| references.targets(citizen, field).next().cloned();
+            let branch = Branch {
+                timeline: citizen.clone(),
+                parent: single("parent"),
+                fork: single("fork"),
+                events: references.targets(citizen, "events").cloned().collect(),
+                sequence: Vec::new(),
+            };
+            branches.index.insert(citizen.clone(), branches.branches.len());
+            branches.branches.push(branch);
+        }
+
+        let mut state = vec![Visit::New; branches.branches.len()];
+        for i in 0..branches.branches.len() {
+            branches.compute_sequence(i, world, &mut state, errors);
+        }
+        branches
+    }
+
+    /// Fill in the history of branch `i`, after those of its ancestors
+    ///
+    /// Returns false if the history is cut short by a `parent` cycle.
+    fn compute_sequence(&mut self, i: usize, world: &World, state: &mut [Visit], errors: &mut Vec<CldError>) -> bool {
+        match state[i] {
+            Visit::Done { complete } => return complete,
+            Visit::Active => {
+                let timeline = &self.branches[i].timeline;
+                errors.push(CldError::rule(
+                    "!Timeline",
+                    format!("Timeline '{}' is its own ancestor through `parent`", timeline.name),
+                    world.name_span(timeline),
+                ));
+                return false;
+            }
+            Visit::New => state[i] = Visit::Active,
+        }
+
+        let branch = self.branches[i].clone();
+        let span = world.name_span(&branch.timeline);
+        let mut sequence = Vec::new();
+        let mut complete = true;
+
+        match (&branch.parent, &branch.fork) {
+            (Some(parent), Some(fork)) => {
+                let parent_index = self.index[parent];
+                complete = self.compute_sequence(parent_index, world, state, errors);
+                let inherited = &self.branches[parent_index].sequence;
+                match inherited.iter().position(|event| event == fork) {
+                    Some(position) => sequence.extend(inherited[..=position].iter().cloned()),
+                    // A history cut short by a cycle has already been reported
+                    None if complete => errors.push(CldError::rule(
+                        "!Fork",
+                        format!(
+                            "Timeline '{}' forks at '{}', which is not part of the history of '{}'",
+                            branch.timeline.name, fork.name, parent.name
+                        ),
+                        span,
+                    )),
+                    None => {}
+                }
+            }
+            (Some(_), None) => errors.push(CldError::rule(
+                "!Fork",
+                format!("Timeline '{}' has a parent but no fork event", branch.timeline.name),
+                span,
+            )),
+            (None, Some(_)) => errors.push(CldError::rule(
+                "!Fork",
+                format!("Timeline '{}' has a fork event but no parent", branch.timeline.name),
+                span,
+            )),
+            (None, None) => {}
+        }
+
+        sequence.extend(branch.events);
+        self.branches[i].sequence = sequence;
+        state[i] = Visit::Done { complete };
+        complete
+    }
+
+    /// All timelines, in declaration order
+    pub fn iter(&self) -> impl Iterator<Item = &Branch> {
+        self.branches.iter()
+    }
+
+    /// The branch of `timeline`
+    pub fn get(&self, timeline: &CitizenRef) -> Option<&Branch> {
+        self.index.get(timeline).map(|&i| &self.branches[i])
+    }
+
+    /// Timelines that split directly from `timeline`
+    pub fn children<'a>(&'a self, timeline: &'a CitizenRef) -> impl Iterator<Item = &'a Branch> + 'a {
+        self.branches
+            .iter()
+            .filter(move |branch| branch.parent.as_ref() == Some(timeline))
+    }
+
+    /// Check which of the `required` CoreEvents each timeline reaches
+    ///
+    /// Only timelines with a history are checked, i.e. ones that list
+    /// events or inherit them from a parent.
+    pub fn convergence(&self, required: &[CitizenRef]) -> Vec<Convergence> {
+        self.branches
+            .iter()
+            .filter(|branch| !branch.sequence.is_empty())
+            .map(|branch| Convergence {
+                timeline: branch.timeline.clone(),
+                missing: required
+                    .iter()
+                    .filter(|anchor| !branch.sequence.contains(anchor))
+                    .cloned()
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Visit {
+    New,
+    Active,
+    Done { complete: bool },
+}
+
+/// Check that every timeline reaches the CoreEvents anchored by the Origin
+pub fn validate_convergence(world: &World, references: &ReferenceGraph, branches: &Branches) -> Vec<CldError> {
+    let mut errors = Vec::new();
+    let required: Vec<CitizenRef> = match &world.origin {
+        Some(origin) => references
+            .targets(&CitizenRef::of(origin), "core_anchors")
+            .cloned()
+            .collect(),
+        None => Vec::new(),
+    };
+
+    for convergence in branches.convergence(&required) {
+        if convergence.converges() {
+            continue;
+        }
+        let timeline = &convergence.timeline;
+        let missing: Vec<&str> = convergence.missing.iter().map(|event| event.name.as_str()).collect();
+        errors.push(CldError::rule(
+            "!Convergence",
+            format!(
+                "Timeline '{}' never reaches the anchored CoreEvent{} '{}'",
+                timeline.name,
+                if missing.len() == 1 { "" } else { "s" },
+                missing.join("', '")
+            ),
+            world.name_span(timeline),
+        ));
+    }
+    errors
+}
+
+/// Where `timeline` lists `event` in its own `events`
+///
+/// Returns `None` if the timeline does not list the event itself, e.g.
+/// because it inherits the event from its parent.
+pub fn listed_event_span(references: &ReferenceGraph, timeline: &CitizenRef, event: &CitizenRef) -> Option<Span> {
+    references
+        .outgoing(timeline)
+        .find(|reference| reference.field == "events" && &reference.to == event)
+        .map(|reference| reference.span)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::CLDParser;
+
+    fn build(source: &str) -> (World, ReferenceGraph, Branches, Vec<CldError>) {
+        let document = CLDParser::parse_document(source).expect("test source parses");
+        let (world, errors) = World::build_document(document);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let references = world.reference_graph();
+        let mut errors = Vec::new();
+        let branches = Branches::build(&world, &references, &mut errors);
+        (world, references, branches, errors)
+    }
+
+    fn timeline(name: &str) -> CitizenRef {
+        CitizenRef {
+            kind: "Timeline".to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    fn history<'a>(branches: &'a Branches, name: &str) -> Vec<&'a str> {
+        let branch = branches.get(&timeline(name)).expect("timeline has a branch");
+        branch.sequence.iter().map(|event| event.name.as_str()).collect()
+    }
+
+    fn messages(errors: &[CldError]) -> Vec<String> {
+        errors
+            .iter()
+            .map(|error| match error {
+                CldError::RuleViolation { rule, message, .. } => format!("{}: {}", rule, message),
+                other => other.to_string(),
+            })
+            .collect()
+    }
+
+    const EVENTS: &str = r#"
+        @Event[e1] { }
+        @Event[e2] { }
+        @Event[e3] { }
+        @Event[x] { }
+        @Event[y] { }
+    "#;
+
+    #[test]
+    fn branches_share_their_parent_history_up_to_the_fork() {
+        let source = format!(
+            "{}{}",
+            EVENTS,
+            r#"
+            @Timeline[deep] { parent: alt fork: x events: [y] }
+            @Timeline[main] { events: [e1, e2, e3] }
+            @Timeline[alt] { parent: main fork: e2 events: [x] }
+            "#
+        );
+        let (_, _, branches, errors) = build(&source);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(history(&branches, "main"), ["e1", "e2", "e3"]);
+        assert_eq!(history(&branches, "alt"), ["e1", "e2", "x"]);
+        // Declared before its parent, and still computed after it
+        assert_eq!(history(&branches, "deep"), ["e1", "e2", "x", "y"]);
+
+        let main = timeline("main");
+        let children: Vec<&str> = branches.children(&main).map(|b| b.timeline.name.as_str()).collect();
+        assert_eq!(children, ["alt"]);
+    }
+
+    #[test]
+    fn forks_must_lie_on_the_parent_history() {
+        let source = format!(
+            "{}{}",
+            EVENTS,
+            r#"
+            @Timeline[main] { events: [e1, e2] }
+            @Timeline[alt] { parent: main fork: x events: [y] }
+            @Timeline[orphan] { fork: e1 events: [y] }
+            @Timeline[stray] { parent: main events: [y] }
+            "#
+        );
+        let (_, _, branches, errors) = build(&source);
+        assert_eq!(
+            messages(&errors),
+            [
+                "!Fork: Timeline 'alt' forks at 'x', which is not part of the history of 'main'",
+                "!Fork: Timeline 'orphan' has a fork event but no parent",
+                "!Fork: Timeline 'stray' has a parent but no fork event",
+            ]
+        );
+        // A bad fork leaves only the timeline's own events
+        assert_eq!(history(&branches, "alt"), ["y"]);
+    }
+
+    #[test]
+    fn parent_cycles_are_reported_once() {
+        let source = format!(
+            "{}{}",
+            EVENTS,
+            r#"
+            @Timeline[a] { parent: b fork: x events: [e1] }
+            @Timeline[b] { parent: a fork: e1 events: [x] }
+            @Timeline[c] { parent: a fork: e1 events: [y] }
+            "#
+        );
+        let (_, _, branches, errors) = build(&source);
+        assert_eq!(messages(&errors), ["!Timeline: Timeline 'a' is its own ancestor through `parent`"]);
+        assert!(branches.get(&timeline("c")).is_some());
+    }
+
+    #[test]
+    fn every_timeline_with_a_history_must_reach_the_anchors() {
+        let source = format!(
+            "{}{}",
+            EVENTS,
+            r#"
+            @Origin[o] { core_anchors: [dawn, dusk] }
+            @CoreEvent[dawn] { }
+            @CoreEvent[dusk] { }
+            @Timeline[main] { events: [dawn, e1, dusk] }
+            @Timeline[alt] { parent: main fork: dawn events: [e2] }
+            @Timeline[empty] { }
+            "#
+        );
+        let (world, references, branches, errors) = build(&source);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let convergence = branches.convergence(&[]);
+        assert_eq!(convergence.len(), 2);
+        assert!(convergence.iter().all(Convergence::converges));
+
+        assert_eq!(
+            messages(&validate_convergence(&world, &references, &branches)),
+            ["!Convergence: Timeline 'alt' never reaches the anchored CoreEvent 'dusk'"]
+        );
+    }
+}
@@ -0,0 +1,504 @@
+//! Causal structure of a world's events
+//!
+//! Events and core events declare what they bring about with
+//! `causes: [...]`, or what brought them about with `caused_by: [...]`.
+//! Both spellings add an edge from cause to effect to a [`CausalGraph`],
+//! which must be acyclic: an event cannot be its own (indirect) cause.
+//!
+//! Timelines order events into histories and may branch from one another;
+//! see [`Branches`].
+
+use crate::diagnostics::Label;
+use crate::error::CldError;
+use crate::resolve::{CitizenRef, ReferenceGraph};
+use crate::span::Span;
+use crate::world::World;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+mod anchor;
+mod branch;
+
+pub use anchor::validate_anchors;
+pub use branch::{listed_event_span, validate_convergence, Branch, Branches, Convergence};
+
+/// A `cause -> effect` edge between two events
+#[derive(Debug, Clone, PartialEq)]
+pub struct CausalEdge {
+    pub cause: CitizenRef,
+    pub effect: CitizenRef,
+    /// The identifier in `causes` or `caused_by` that declared the edge
+    pub span: Span,
+}
+
+/// The events of a world and the causal edges between them
+///
+/// Events are kept in declaration order, which is also the order used to
+/// break ties wherever the graph leaves a choice, so every query gives the
+/// same answer from run to run.
+#[derive(Debug, Clone, Default)]
+pub struct CausalGraph {
+    events: Vec<CitizenRef>,
+    index: HashMap<CitizenRef, usize>,
+    edges: Vec<CausalEdge>,
+    /// Indices of each event's effects, in order of declaration
+    effects: Vec<Vec<usize>>,
+    /// Indices of each event's causes, in order of declaration
+    causes: Vec<Vec<usize>>,
+}
+
+impl CausalGraph {
+    /// Build the causal graph of `world`
+    ///
+    /// References that do not resolve are left out; validation reports them.
+    pub fn build(world: &World) -> Self {
+        Self::from_references(world, &world.reference_graph())
+    }
+
+    /// Build the causal graph from an already resolved reference graph
+    pub fn from_references(world: &World, references: &ReferenceGraph) -> Self {
+        let mut graph = CausalGraph::default();
+        for citizen in world.declaration_order() {
+            if citizen.kind == "Event" || citizen.kind == "CoreEvent" {
+                graph.index.insert(citizen.clone(), graph.events.len());
+                graph.events.push(citizen.clone());
+            }
+        }
+        graph.effects = vec![Vec::new(); graph.events.len()];
+        graph.causes = vec![Vec::new(); graph.events.len()];
+
+        for reference in &references.references {
+            let (cause, effect) = match reference.field.as_str() {
+                "causes" => (&reference.from, &reference.to),
+                "caused_by" => (&reference.to, &reference.from),
+                _ => continue,
+            };
+            graph.add_edge(cause, effect, reference.span);
+        }
+        graph
+    }
+
+    /// Add an edge unless it is already present
+    fn add_edge(&mut self, cause: &CitizenRef, effect: &CitizenRef, span: Span) {
+        let (Some(&from), Some(&to)) = (self.index.get(cause), self.index.get(effect)) else {
+            return;
+        };
+        if self.effects[from].contains(&to) {
+            return;
+        }
+        self.effects[from].push(to);
+        self.causes[to].push(from);
+        self.edges.push(CausalEdge {
+            cause: cause.clone(),
+            effect: effect.clone(),
+            span,
+        });
+    }
+
+    /// All events, in declaration order
+    pub fn events(&self) -> &[CitizenRef] {
+        &self.events
+    }
+
+    /// All edges, in the order they were declared
+    pub fn edges(&self) -> &[CausalEdge] {
+        &self.edges
+    }
+
+    /// Whether `event` is part of the graph
+    pub fn contains(&self, event: &CitizenRef) -> bool {
+        self.index.contains_key(event)
+    }
+
+    /// Direct causes of `event`
+    pub fn causes_of(&self, event: &CitizenRef) -> Vec<&CitizenRef> {
+        self.neighbours(event, &self.causes)
+    }
+
+    /// Direct effects of `event`
+    pub fn effects_of(&self, event: &CitizenRef) -> Vec<&CitizenRef> {
+        self.neighbours(event, &self.effects)
+    }
+
+    fn neighbours<'a>(&'a self, event: &CitizenRef, adjacency: &[Vec<usize>]) -> Vec<&'a CitizenRef> {
+        match self.index.get(event) {
+            Some(&i) => adjacency[i].iter().map(|&j| &self.events[j]).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Every event that `event` follows from, directly or indirectly, in
+    /// declaration order
+    pub fn ancestors(&self, event: &CitizenRef) -> Vec<&CitizenRef> {
+        self.reachable(event, &self.causes)
+    }
+
+    /// Every event that follows from `event`, directly or indirectly, in
+    /// declaration order
+    pub fn descendants(&self, event: &CitizenRef) -> Vec<&CitizenRef> {
+        self.reachable(event, &self.effects)
+    }
+
+    fn reachable<'a>(&'a self, event: &CitizenRef, adjacency: &[Vec<usize>]) -> Vec<&'a CitizenRef> {
+        let Some(&start) = self.index.get(event) else {
+            return Vec::new();
+        };
+        let mut seen = vec![false; self.events.len()];
+        let mut queue = VecDeque::from([start]);
+        while let Some(i) = queue.pop_front() {
+            for &j in &adjacency[i] {
+                if !seen[j] {
+                    seen[j] = true;
+                    queue.push_back(j);
+                }
+            }
+        }
+        // An event on a cycle reaches itself, but is not its own ancestor
+        seen[start] = false;
+        (0..self.events.len())
+            .filter(|&i| seen[i])
+            .map(|i| &self.events[i])
+            .collect()
+    }
+
+    /// Whether `cause` leads to `effect` through one or more edges
+    pub fn precedes(&self, cause: &CitizenRef, effect: &CitizenRef) -> bool {
+        self.descendants(cause).contains(&effect)
+    }
+
+    /// The earliest consistent ordering of all events
+    ///
+    /// Every cause comes before its effects; among events that could come
+    /// next, the one declared first is taken. Returns the cycles instead if
+    /// there is no such ordering.
+    pub fn topological_order(&self) -> Result<Vec<&CitizenRef>, Vec<Vec<&CitizenRef>>> {
+        self.order(&self.effects, |_| false).ok_or_else(|| self.cycles())
+    }
+
+    /// The earliest ordering that respects causality and `constraints`
+    ///
+    /// Each `(before, after)` pair of `constraints` is kept in order as if
+    /// it were a causal edge. Among events that could come next, those
+    /// `first` selects are taken before the others, and then the one
+    /// declared first. Returns `None` if causality and the constraints
+    /// cannot all be met.
+    pub fn constrained_order(
+        &self,
+        constraints: &[(CitizenRef, CitizenRef)],
+        first: impl Fn(&CitizenRef) -> bool,
+    ) -> Option<Vec<&CitizenRef>> {
+        let mut effects = self.effects.clone();
+        for (before, after) in constraints {
+            if let (Some(&from), Some(&to)) = (self.index.get(before), self.index.get(after)) {
+                if !effects[from].contains(&to) {
+                    effects[from].push(to);
+                }
+            }
+        }
+        self.order(&effects, first)
+    }
+
+    /// Kahn's algorithm over `effects`, or `None` if it has a cycle
+    fn order(&self, effects: &[Vec<usize>], first: impl Fn(&CitizenRef) -> bool) -> Option<Vec<&CitizenRef>> {
+        let mut pending = vec![0; self.events.len()];
+        for &j in effects.iter().flatten() {
+            pending[j] += 1;
+        }
+        // Preferred events sort before the others, then by declaration
+        let key = |i: usize| Reverse((!first(&self.events[i]), i));
+        let mut ready: BinaryHeap<_> = (0..self.events.len()).filter(|&i| pending[i] == 0).map(key).collect();
+        let mut order = Vec::with_capacity(self.events.len());
+
+        while let Some(Reverse((_, i))) = ready.pop() {
+            order.push(&self.events[i]);
+            for &j in &effects[i] {
+                pending[j] -= 1;
+                if pending[j] == 0 {
+                    ready.push(key(j));
+                }
+            }
+        }
+
+        (order.len() == self.events.len()).then_some(order)
+    }
+
+    /// One cycle through each group of mutually dependent events
+    ///
+    /// Each cycle starts at the first-declared event of its group and lists
+    /// the events in causal order, without repeating the first one.
+    pub fn cycles(&self) -> Vec<Vec<&CitizenRef>> {
+        self.cycle_indices()
+            .into_iter()
+            .map(|cycle| cycle.into_iter().map(|i| &self.events[i]).collect())
+            .collect()
+    }
+
+    fn cycle_indices(&self) -> Vec<Vec<usize>> {
+        let components = self.strongly_connected_components();
+        let mut cycles = Vec::new();
+        for component in &components {
+            let start = component[0];
+            let in_component = |i: &usize| component.contains(i);
+            if component.len() == 1 && !self.effects[start].contains(&start) {
+                continue;
+            }
+
+            // Shortest path from the start back to itself within the component
+            let mut parent: HashMap<usize, usize> = HashMap::new();
+            let mut queue = VecDeque::from([start]);
+            let mut last = None;
+            'search: while let Some(i) = queue.pop_front() {
+                for &j in self.effects[i].iter().filter(|j| in_component(j)) {
+                    if j == start {
+                        last = Some(i);
+                        break 'search;
+                    }
+                    if let std::collections::hash_map::Entry::Vacant(entry) = parent.entry(j) {
+                        entry.insert(i);
+                        queue.push_back(j);
+                    }
+                }
+            }
+
+            let mut cycle = Vec::new();
+            let mut current = last;
+            while let Some(i) = current {
+                cycle.push(i);
+                current = if i == start { None } else { parent.get(&i).copied() };
+            }
+            cycle.reverse();
+            cycles.push(cycle);
+        }
+        cycles.sort_by_key(|cycle| cycle[0]);
+        cycles
+    }
+
+    /// Tarjan's algorithm; each component is sorted by declaration order
+    ///
+    /// The depth-first search keeps its own stack of events and the next
+    /// effect to visit from each, so long causal chains cannot overflow the
+    /// call stack.
+    fn strongly_connected_components(&self) -> Vec<Vec<usize>> {
+        let n = self.events.len();
+        let mut next = 0;
+        let mut index: Vec<Option<usize>> = vec![None; n];
+        let mut low = vec![0; n];
+        let mut stack = Vec::new();
+        let mut on_stack = vec![false; n];
+        let mut components = Vec::new();
+
+        for root in 0..n {
+            if index[root].is_some() {
+                continue;
+            }
+            let mut path = vec![(root, 0)];
+            index[root] = Some(next);
+            low[root] = next;
+            next += 1;
+            stack.push(root);
+            on_stack[root] = true;
+
+            while let Some(&mut (i, ref mut position)) = path.last_mut() {
+                if let Some(&j) = self.effects[i].get(*position) {
+                    *position += 1;
+                    match index[j] {
+                        None => {
+                            index[j] = Some(next);
+                            low[j] = next;
+                            next += 1;
+                            stack.push(j);
+                            on_stack[j] = true;
+                            path.push((j, 0));
+                        }
+                        Some(index) if on_stack[j] => low[i] = low[i].min(index),
+                        Some(_) => {}
+                    }
+                    continue;
+                }
+
+                path.pop();
+                if let Some(&(parent, _)) = path.last() {
+                    low[parent] = low[parent].min(low[i]);
+                }
+                if Some(low[i]) == index[i] {
+                    let mut component = Vec::new();
+                    while let Some(j) = stack.pop() {
+                        on_stack[j] = false;
+                        component.push(j);
+                        if j == i {
+                            break;
+                        }
+                    }
+                    component.sort();
+                    components.push(component);
+                }
+            }
+        }
+        components
+    }
+
+    /// The edge from `cause` to `effect`, by index
+    fn edge(&self, cause: usize, effect: usize) -> Option<&CausalEdge> {
+        self.edges
+            .iter()
+            .find(|edge| edge.cause == self.events[cause] && edge.effect == self.events[effect])
+    }
+}
+
+/// Report every causal cycle of `graph`, at the edges that form it
+pub fn validate_causality(graph: &CausalGraph) -> Vec<CldError> {
+    graph
+        .cycle_indices()
+        .into_iter()
+        .map(|cycle| {
+            // Pair each event with the edge leading to the next one
+            let edges: Vec<&CausalEdge> = cycle
+                .iter()
+                .zip(cycle.iter().cycle().skip(1))
+                .filter_map(|(&cause, &effect)| graph.edge(cause, effect))
+                .collect();
+            let span = edges.first().map(|edge| edge.span).unwrap_or_default();
+            CldError::CausalCycle {
+                events: cycle.iter().map(|&i| graph.events[i].name.clone()).collect(),
+                span,
+                related: edges
+                    .iter()
+                    .skip(1)
+                    .map(|edge| Label {
+                        span: edge.span,
+                        message: format!("{} causes {}", edge.cause.name, edge.effect.name),
+                        primary: false,
+                    })
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::CLDParser;
+
+    fn graph(source: &str) -> CausalGraph {
+        let document = CLDParser::parse_document(source).expect("test source parses");
+        let (world, errors) = World::build_document(document);
+        assert!(errors.is_empty(), "{:?}", errors);
+        CausalGraph::build(&world)
+    }
+
+    fn names(events: &[&CitizenRef]) -> Vec<String> {
+        events.iter().map(|event| event.name.clone()).collect()
+    }
+
+    fn event(name: &str) -> CitizenRef {
+        CitizenRef {
+            kind: "Event".to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn groups_mutually_dependent_events_into_components() {
+        let graph = graph(
+            r#"
+            @Event[a] { causes: [b] }
+            @Event[b] { causes: [c] }
+            @Event[c] { causes: [a, d] }
+            @Event[d] { causes: [e] }
+            @Event[e] { causes: [d] }
+            @Event[f] { causes: [f] }
+            @Event[g] { }
+            "#,
+        );
+        let mut components = graph.strongly_connected_components();
+        components.sort();
+        assert_eq!(components, [vec![0, 1, 2], vec![3, 4], vec![5], vec![6]]);
+    }
+
+    #[test]
+    fn finds_components_of_long_chains_without_recursing() {
+        let n = 100_000;
+        let events: Vec<CitizenRef> = (0..n)
+            .map(|i| CitizenRef {
+                kind: "Event".to_string(),
+                name: format!("e{}", i),
+            })
+            .collect();
+        // A chain through every event, closed into one cycle by the last
+        let effects: Vec<Vec<usize>> = (0..n).map(|i| vec![(i + 1) % n]).collect();
+        let causes: Vec<Vec<usize>> = (0..n).map(|i| vec![(i + n - 1) % n]).collect();
+        let graph = CausalGraph {
+            index: events.iter().cloned().zip(0..).collect(),
+            events,
+            edges: Vec::new(),
+            effects,
+            causes,
+        };
+
+        let components = graph.strongly_connected_components();
+        assert_eq!(components.len(), 1);
+        assert_eq!(components[0].len(), n);
+    }
+
+    #[test]
+    fn reports_one_cycle_per_component_from_its_first_event() {
+        let graph = graph(
+            r#"
+            @Event[d] { causes: [e] }
+            @Event[a] { causes: [b] }
+            @Event[b] { causes: [c] }
+            @Event[c] { causes: [a] caused_by: [d] }
+            @Event[e] { causes: [d] }
+            @Event[self] { causes: [self] }
+            @Event[free] { }
+            "#,
+        );
+        let cycles: Vec<Vec<String>> = graph.cycles().iter().map(|cycle| names(cycle)).collect();
+        assert_eq!(cycles, [vec!["d", "e"], vec!["a", "b", "c"], vec!["self"]]);
+        assert!(graph.topological_order().is_err());
+
+        let errors = validate_causality(&graph);
+        assert_eq!(errors.len(), 3);
+        let CldError::CausalCycle { events, related, .. } = &errors[1] else {
+            panic!("expected a causal cycle, got {:?}", errors[1]);
+        };
+        assert_eq!(events, &["a", "b", "c"]);
+        assert_eq!(related.len(), 2);
+    }
+
+    #[test]
+    fn orders_causes_first_and_otherwise_by_declaration() {
+        let graph = graph(
+            r#"
+            @Event[stars] { caused_by: [dust, big_bang] }
+            @Event[drift] { }
+            @Event[dust] { }
+            @CoreEvent[big_bang] { causes: [dust] }
+            "#,
+        );
+        let order = graph.topological_order().expect("the graph is acyclic");
+        assert_eq!(names(&order), ["drift", "big_bang", "dust", "stars"]);
+        assert!(graph.precedes(order[1], order[3]));
+        assert!(!graph.precedes(order[3], order[1]));
+    }
+
+    #[test]
+    fn constrained_orders_add_edges_and_preferences() {
+        let graph = graph(
+            r#"
+            @Event[a] { }
+            @Event[b] { }
+            @Event[c] { causes: [b] }
+            "#,
+        );
+        let order = graph.constrained_order(&[(event("b"), event("a"))], |_| false).expect("consistent");
+        assert_eq!(names(&order), ["c", "b", "a"]);
+
+        let order = graph.constrained_order(&[], |event| event.name == "c").expect("consistent");
+        assert_eq!(names(&order), ["c", "a", "b"]);
+
+        // A constraint against causality cannot be met
+        assert!(graph.constrained_order(&[(event("b"), event("c"))], |_| false).is_none());
+    }
+}
@@ -0,0 +1,561 @@
+//! Typed views of citizens
+//!
+//! The parser produces citizens whose fields are an untyped [`Fields`] bag.
+//! Lowering turns each citizen into a typed struct: well-known fields are
+//! checked against the world's [`Schema`] and extracted, and everything else
+//! is kept in `extensions` so no information is lost.
+
+use crate::citizens::*;
+use crate::error::CldError;
+use crate::resolve::CitizenRef;
+use crate::schema::Schema;
+use crate::world::{Store, World};
+use serde::{Deserialize, Serialize};
+
+/// The emotional peak of an event
+///
+/// Written either as a bare label (`emotion_peak: "wonder"`) or as a map with
+/// an intensity from 0 to 100 (`emotion_peak: { label: wonder, intensity: 80 }`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Emotion {
+    pub label: String,
+    pub intensity: Option<f64>,
+}
+
+/// Typed view of an `@Origin`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TypedOrigin {
+    pub name: String,
+    pub entropy: Option<f64>,
+    /// Global entropy bounds
+    pub min_entropy: Option<f64>,
+    pub max_entropy: Option<f64>,
+    /// Fraction of entropy above `min_entropy` lost every tick
+    pub entropy_decay: Option<f64>,
+    /// `saturate`, `reject` or `none`
+    pub entropy_clamp: Option<String>,
+    /// Seed for the run's random numbers, unless overridden
+    pub seed: Option<u64>,
+    /// Emotions added to or overriding the built-in vocabulary
+    pub emotions: Fields,
+    pub core_anchors: Vec<String>,
+    pub description: Option<String>,
+    pub extensions: Fields,
+}
+
+/// Typed view of a `@Timeline`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TypedTimeline {
+    pub name: String,
+    pub origin: Option<String>,
+    /// The timeline this branch splits from
+    pub parent: Option<String>,
+    /// The parent's event after which this branch splits
+    pub fork: Option<String>,
+    /// The timeline's own events, in order
+    pub events: Vec<String>,
+    pub description: Option<String>,
+    pub extensions: Fields,
+}
+
+/// Typed view of an `@Event` or `@CoreEvent`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TypedEvent {
+    pub name: String,
+    /// Change applied to world entropy when the event fires; 0 if unset
+    pub entropy_change: f64,
+    pub emotion_peak: Option<Emotion>,
+    pub description: Option<String>,
+    /// Condition under which the event may fire, as written
+    pub requires: Option<String>,
+    /// Probability that the event happens when it is due; certain if unset
+    pub chance: Option<f64>,
+    /// Structured consequences, e.g. `effects: { star_count: 100 }`
+    pub effects: Fields,
+    /// Events this event brings about, as written
+    pub causes: Vec<String>,
+    /// Events that bring this event about, as written
+    pub caused_by: Vec<String>,
+    /// Labels that era rules match against, e.g. `tags: [violence]`
+    pub tags: Vec<String>,
+    /// Capabilities the active era must grant for the event to occur
+    pub capabilities: Vec<String>,
+    /// Whether this was declared as a `@CoreEvent`
+    pub is_core: bool,
+    pub extensions: Fields,
+}
+
+/// Typed view of a `@Niche`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TypedNiche {
+    pub name: String,
+    pub strategy: Option<String>,
+    pub stress_response: Option<String>,
+    pub description: Option<String>,
+    pub extensions: Fields,
+}
+
+/// Typed view of an `@Era`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TypedEra {
+    pub name: String,
+    pub start: Option<String>,
+    pub end: Option<String>,
+    /// The timeline the era is placed on, if given explicitly
+    pub timeline: Option<String>,
+    pub rules: Option<String>,
+    /// Tags an event in the era may carry; any tag if unset
+    pub allowed_tags: Option<Vec<String>>,
+    /// Tags no event in the era may carry
+    pub forbidden_tags: Vec<String>,
+    /// Capabilities the era grants; any capability if unset
+    pub capabilities: Option<Vec<String>>,
+    pub min_entropy: Option<f64>,
+    pub max_entropy: Option<f64>,
+    pub description: Option<String>,
+    pub extensions: Fields,
+}
+
+/// Typed view of a `>>Generator`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TypedGenerator {
+    pub name: String,
+    pub source: Option<String>,
+    pub output_type: Option<String>,
+    pub description: Option<String>,
+    pub extensions: Fields,
+}
+
+/// Typed view of a `@Memory`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TypedMemory {
+    pub name: String,
+    pub storage_model: Option<String>,
+    pub retention_policy: Option<String>,
+    pub description: Option<String>,
+    pub extensions: Fields,
+}
+
+/// Typed view of an `@Immune`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TypedImmune {
+    pub name: String,
+    pub monitor_metric: Option<String>,
+    pub threshold: Option<f64>,
+    pub action: Option<String>,
+    pub description: Option<String>,
+    pub extensions: Fields,
+}
+
+/// A world whose citizens have all been lowered to typed views
+///
+/// Like [`World`], it keeps core events apart from regular ones, since an
+/// `@Event` and a `@CoreEvent` may share a name.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TypedWorld {
+    pub origin: Option<TypedOrigin>,
+    pub timelines: Store<TypedTimeline>,
+    pub events: Store<TypedEvent>,
+    pub core_events: Store<TypedEvent>,
+    pub niches: Store<TypedNiche>,
+    pub eras: Store<TypedEra>,
+    pub generators: Store<TypedGenerator>,
+    pub memories: Store<TypedMemory>,
+    pub immunes: Store<TypedImmune>,
+}
+
+impl TypedWorld {
+    /// The `@Event` or `@CoreEvent` that `event` refers to
+    pub fn event(&self, event: &CitizenRef) -> Option<&TypedEvent> {
+        match event.kind.as_str() {
+            "Event" => self.events.get(&event.name),
+            "CoreEvent" => self.core_events.get(&event.name),
+            _ => None,
+        }
+    }
+}
+
+/// Lower every citizen of `world` to its typed view, following `world.schema`
+///
+/// Fields the schema rejects are treated as unset; [`Schema::check`] reports
+/// them. Fields the schema accepts but a typed view cannot hold, such as a
+/// well-known field a `@Schema` gives another type, are kept in
+/// `extensions`. The returned errors are the problems the schema cannot
+/// express, e.g. a fractional `seed`, so the returned world is always
+/// complete.
+pub fn lower_world(world: &World) -> (TypedWorld, Vec<CldError>) {
+    let schema = &world.schema;
+    let mut errors = Vec::new();
+    let mut typed = TypedWorld::default();
+
+    // Lower in declaration order so that both the typed tables and the
+    // diagnostics come out in source order
+    for citizen in world.declaration_order() {
+        let name = citizen.name.as_str();
+        match citizen.kind.as_str() {
+            "Origin" => typed.origin = world.origin.as_ref().map(|origin| lower_origin(origin, schema, &mut errors)),
+            "Timeline" => {
+                if let Some(timeline) = world.timelines.get(name) {
+                    typed.timelines.insert(name, lower_timeline(timeline, schema, &mut errors));
+                }
+            }
+            "Event" => {
+                if let Some(event) = world.events.get(name) {
+                    typed.events.insert(name, lower_event(event, false, schema, &mut errors));
+                }
+            }
+            "CoreEvent" => {
+                if let Some(core_event) = world.core_events.get(name) {
+                    typed.core_events.insert(name, lower_event(core_event, true, schema, &mut errors));
+                }
+            }
+            "Niche" => {
+                if let Some(niche) = world.niches.get(name) {
+                    typed.niches.insert(name, lower_niche(niche, schema, &mut errors));
+                }
+            }
+            "Era" => {
+                if let Some(era) = world.eras.get(name) {
+                    typed.eras.insert(name, lower_era(era, schema, &mut errors));
+                }
+            }
+            "Generator" => {
+                if let Some(generator) = world.generators.get(name) {
+                    typed.generators.insert(name, lower_generator(generator, schema, &mut errors));
+                }
+            }
+            "Memory" => {
+                if let Some(memory) = world.memories.get(name) {
+                    typed.memories.insert(name, lower_memory(memory, schema, &mut errors));
+                }
+            }
+            "Immune" => {
+                if let Some(immune) = world.immunes.get(name) {
+                    typed.immunes.insert(name, lower_immune(immune, schema, &mut errors));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    (typed, errors)
+}
+
+/// Lower an `@Origin`
+pub fn lower_origin(origin: &Origin, schema: &Schema, errors: &mut Vec<CldError>) -> TypedOrigin {
+    let mut reader = FieldReader::new(origin, schema, errors);
+    TypedOrigin {
+        name: origin.name.clone(),
+        entropy: reader.number("entropy"),
+        min_entropy: reader.number("min_entropy"),
+        max_entropy: reader.number("max_entropy"),
+        entropy_decay: reader.number("entropy_decay"),
+        entropy_clamp: reader.text("entropy_clamp"),
+        seed: reader.whole_number("seed"),
+        emotions: reader.map("emotions"),
+        core_anchors: reader.identifier_list("core_anchors"),
+        description: reader.text("description"),
+        extensions: reader.extensions(),
+    }
+}
+
+/// Lower a `@Timeline`
+pub fn lower_timeline(timeline: &Timeline, schema: &Schema, errors: &mut Vec<CldError>) -> TypedTimeline {
+    let mut reader = FieldReader::new(timeline, schema, errors);
+    TypedTimeline {
+        name: timeline.name.clone(),
+        origin: reader.identifier("origin"),
+        parent: reader.identifier("parent"),
+        fork: reader.identifier("fork"),
+        events: reader.identifier_list("events"),
+        description: reader.text("description"),
+        extensions: reader.extensions(),
+    }
+}
+
+/// Lower an `@Event` or `@CoreEvent`
+pub fn lower_event(event: &dyn CitizenData, is_core: bool, schema: &Schema, errors: &mut Vec<CldError>) -> TypedEvent {
+    let mut reader = FieldReader::new(event, schema, errors);
+    TypedEvent {
+        name: event.name().to_string(),
+        entropy_change: reader.number("entropy_change").unwrap_or(0.0),
+        emotion_peak: reader.emotion("emotion_peak"),
+        description: reader.text("description"),
+        requires: reader.text("requires"),
+        chance: reader.number("chance"),
+        effects: reader.map("effects"),
+        causes: reader.identifier_list("causes"),
+        caused_by: reader.identifier_list("caused_by"),
+        tags: reader.identifier_list("tags"),
+        capabilities: reader.identifier_list("capabilities"),
+        is_core,
+        extensions: reader.extensions(),
+    }
+}
+
+/// Lower a `@Niche`
+pub fn lower_niche(niche: &Niche, schema: &Schema, errors: &mut Vec<CldError>) -> TypedNiche {
+    let mut reader = FieldReader::new(niche, schema, errors);
+    TypedNiche {
+        name: niche.name.clone(),
+        strategy: reader.text("strategy"),
+        stress_response: reader.text("stress_response"),
+        description: reader.text("description"),
+        extensions: reader.extensions(),
+    }
+}
+
+/// Lower an `@Era`
+pub fn lower_era(era: &Era, schema: &Schema, errors: &mut Vec<CldError>) -> TypedEra {
+    let mut reader = FieldReader::new(era, schema, errors);
+    TypedEra {
+        name: era.name.clone(),
+        start: reader.identifier("start"),
+        end: reader.identifier("end"),
+        timeline: reader.identifier("timeline"),
+        rules: reader.text("rules"),
+        allowed_tags: reader.optional_identifier_list("allowed_tags"),
+        forbidden_tags: reader.identifier_list("forbidden_tags"),
+        capabilities: reader.optional_identifier_list("capabilities"),
+        min_entropy: reader.number("min_entropy"),
+        max_entropy: reader.number("max_entropy"),
+        description: reader.text("description"),
+        extensions: reader.extensions(),
+    }
+}
+
+/// Lower a `>>Generator`
+pub fn lower_generator(generator: &Generator, schema: &Schema, errors: &mut Vec<CldError>) -> TypedGenerator {
+    let mut reader = FieldReader::new(generator, schema, errors);
+    TypedGenerator {
+        name: generator.name.clone(),
+        source: reader.text("source"),
+        output_type: reader.identifier("output_type"),
+        description: reader.text("description"),
+        extensions: reader.extensions(),
+    }
+}
+
+/// Lower a `@Memory`
+pub fn lower_memory(memory: &Memory, schema: &Schema, errors: &mut Vec<CldError>) -> TypedMemory {
+    let mut reader = FieldReader::new(memory, schema, errors);
+    TypedMemory {
+        name: memory.name.clone(),
+        storage_model: reader.text("storage_model"),
+        retention_policy: reader.text("retention_policy"),
+        description: reader.text("description"),
+        extensions: reader.extensions(),
+    }
+}
+
+/// Lower an `@Immune`
+pub fn lower_immune(immune: &Immune, schema: &Schema, errors: &mut Vec<CldError>) -> TypedImmune {
+    let mut reader = FieldReader::new(immune, schema, errors);
+    TypedImmune {
+        name: immune.name.clone(),
+        monitor_metric: reader.text("monitor_metric"),
+        threshold: reader.number("threshold"),
+        action: reader.text("action"),
+        description: reader.text("description"),
+        extensions: reader.extensions(),
+    }
+}
+
+/// Extracts typed fields from a citizen, remembering which keys were used
+struct FieldReader<'a> {
+    citizen: &'a dyn CitizenData,
+    schema: &'a Schema,
+    used: Vec<&'static str>,
+    errors: &'a mut Vec<CldError>,
+}
+
+impl<'a> FieldReader<'a> {
+    fn new(citizen: &'a dyn CitizenData, schema: &'a Schema, errors: &'a mut Vec<CldError>) -> Self {
+        FieldReader {
+            citizen,
+            schema,
+            used: Vec::new(),
+            errors,
+        }
+    }
+
+    /// The field `key`, if present and of the type the schema declares
+    ///
+    /// A field the schema rejects is marked as used and treated as unset.
+    fn take(&mut self, key: &'static str) -> Option<&'a Field> {
+        let field = self.citizen.fields().field(key)?;
+        let declared = self.schema.field(self.citizen.kind(), key);
+        if declared.is_some_and(|declared| declared.ty.check(&field.value).is_err()) {
+            self.used.push(key);
+            return None;
+        }
+        Some(field)
+    }
+
+    /// The field `key` as read by `convert`, marking it used if it converts
+    fn read<T>(&mut self, key: &'static str, convert: impl FnOnce(&'a Value) -> Option<T>) -> Option<T> {
+        let value = convert(&self.take(key)?.value.node)?;
+        self.used.push(key);
+        Some(value)
+    }
+
+    fn number(&mut self, key: &'static str) -> Option<f64> {
+        self.read(key, Value::as_number)
+    }
+
+    /// A non-negative integer, written as a number without a fraction
+    fn whole_number(&mut self, key: &'static str) -> Option<u64> {
+        let field = self.take(key)?;
+        let number = field.value.as_number()?;
+        self.used.push(key);
+        if number >= 0.0 && number.fract() == 0.0 && number <= u64::MAX as f64 {
+            return Some(number as u64);
+        }
+        self.errors.push(CldError::TypeMismatch {
+            field: format!("{}[{}].{}", self.citizen.kind(), self.citizen.name(), field.key),
+            expected: "a whole number".to_string(),
+            found: number.to_string(),
+            span: field.value.span,
+        });
+        None
+    }
+
+    /// Free text, written either as a string or as a bare identifier
+    fn text(&mut self, key: &'static str) -> Option<String> {
+        self.read(key, |value| match value {
+            Value::String(text) | Value::Identifier(text) => Some(text.clone()),
+            _ => None,
+        })
+    }
+
+    /// A reference to another citizen (or kind), written as an identifier
+    fn identifier(&mut self, key: &'static str) -> Option<String> {
+        self.read(key, |value| value.as_identifier().cloned())
+    }
+
+    fn identifier_list(&mut self, key: &'static str) -> Vec<String> {
+        self.optional_identifier_list(key).unwrap_or_default()
+    }
+
+    /// A list of identifiers, telling an absent field from an empty list
+    fn optional_identifier_list(&mut self, key: &'static str) -> Option<Vec<String>> {
+        self.read(key, |value| {
+            value
+                .as_list()?
+                .iter()
+                .map(|item| item.as_identifier().cloned())
+                .collect()
+        })
+    }
+
+    fn map(&mut self, key: &'static str) -> Fields {
+        self.read(key, |value| value.as_map().cloned()).unwrap_or_default()
+    }
+
+    fn emotion(&mut self, key: &'static str) -> Option<Emotion> {
+        self.read(key, |value| match value {
+            Value::String(label) | Value::Identifier(label) => Some(Emotion {
+                label: label.clone(),
+                intensity: None,
+            }),
+            Value::Map(map) => {
+                let (Some(Value::String(label)) | Some(Value::Identifier(label))) = map.get("label") else {
+                    return None;
+                };
+                let intensity = match map.get("intensity") {
+                    None => None,
+                    Some(Value::Number(intensity)) => Some(*intensity),
+                    Some(_) => return None,
+                };
+                Some(Emotion {
+                    label: label.clone(),
+                    intensity,
+                })
+            }
+            _ => None,
+        })
+    }
+
+    /// All fields that no typed accessor asked for
+    fn extensions(self) -> Fields {
+        let mut extensions = Fields::new();
+        for field in self.citizen.fields() {
+            if !self.used.contains(&field.key.as_str()) {
+                extensions.push(field.clone());
+            }
+        }
+        extensions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::CLDParser;
+
+    fn lower(source: &str) -> (TypedWorld, Vec<CldError>) {
+        let document = CLDParser::parse_document(source).expect("test source parses");
+        let (world, errors) = World::build_document(document);
+        assert!(errors.is_empty(), "{:?}", errors);
+        lower_world(&world)
+    }
+
+    fn event_ref(kind: &str, name: &str) -> CitizenRef {
+        CitizenRef {
+            kind: kind.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn events_and_core_events_sharing_a_name_are_both_kept() {
+        let (typed, errors) = lower(
+            r#"
+            @CoreEvent[war] { emotion_peak: rage entropy_change: 0.2 }
+            @Event[war] { emotion_peak: calm }
+            "#,
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let core = typed.event(&event_ref("CoreEvent", "war")).expect("core event is lowered");
+        assert!(core.is_core);
+        assert_eq!(core.entropy_change, 0.2);
+        assert_eq!(core.emotion_peak.as_ref().map(|e| e.label.as_str()), Some("rage"));
+
+        let event = typed.event(&event_ref("Event", "war")).expect("event is lowered");
+        assert!(!event.is_core);
+        assert_eq!(event.emotion_peak.as_ref().map(|e| e.label.as_str()), Some("calm"));
+
+        assert!(typed.event(&event_ref("Era", "war")).is_none());
+    }
+
+    #[test]
+    fn lowering_follows_custom_schemas() {
+        let (typed, errors) = lower(
+            r#"
+            @Schema[Event] { entropy_change: text }
+            @Event[e] { entropy_change: high emotion_peak: 3 }
+            "#,
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let event = typed.event(&event_ref("Event", "e")).expect("event is lowered");
+        // Accepted by the schema but not a number: kept as an extension
+        assert_eq!(event.entropy_change, 0.0);
+        assert_eq!(event.extensions.get("entropy_change"), Some(&Value::Identifier("high".to_string())));
+        // Rejected by the schema, which reports it: unset and not kept
+        assert_eq!(event.emotion_peak, None);
+        assert!(!event.extensions.contains_key("emotion_peak"));
+    }
+
+    #[test]
+    fn reports_what_the_schema_cannot_express() {
+        let (typed, errors) = lower("@Origin[o] { seed: 1.5 }");
+        assert_eq!(typed.origin.and_then(|origin| origin.seed), None);
+        assert!(
+            matches!(&errors[..], [CldError::TypeMismatch { expected, .. }] if expected == "a whole number"),
+            "{:?}",
+            errors
+        );
+    }
+}
@@ -3,21 +3,62 @@
 //! This module provides the world representation and validation functionality.
 
 use crate::citizens::*;
-use crate::parser::Citizen;
+use crate::diagnostics::{Label, Severity};
+use crate::emotion::validate_emotions;
+use crate::era::{validate_era_rules, validate_eras, Eras};
+use crate::error::CldError;
+use crate::expr::validate_expressions;
+use crate::parser::{Citizen, Document};
+use crate::resolve::{resolve, CitizenRef, ReferenceGraph};
+use crate::runtime::validate_entropy;
+use crate::schema::{Schema, CITIZEN_KINDS};
+use crate::span::Span;
+use crate::timeline::{validate_anchors, validate_causality, validate_convergence, Branches, CausalGraph};
+use crate::typed::lower_world;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+mod store;
+
+pub use store::Store;
+
+/// Stable identifier of a citizen: its position in declaration order
+///
+/// A `merge` or `override` keeps the id of the declaration it patches, so
+/// ids only depend on which names are declared and in what order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CitizenId(pub usize);
+
+impl std::fmt::Display for CitizenId {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
 /// Represents a complete CLD world with all its citizens
 #[derive(Debug)]
 pub struct World {
     pub origin: Option<Origin>,
-    pub timelines: HashMap<String, Timeline>,
-    pub events: HashMap<String, Event>,
-    pub core_events: HashMap<String, CoreEvent>,
-    pub niches: HashMap<String, Niche>,
-    pub eras: HashMap<String, Era>,
-    pub generators: HashMap<String, Generator>,
-    pub memories: HashMap<String, Memory>,
-    pub immunes: HashMap<String, Immune>,
+    pub timelines: Store<Timeline>,
+    pub events: Store<Event>,
+    pub core_events: Store<CoreEvent>,
+    pub niches: Store<Niche>,
+    pub eras: Store<Era>,
+    pub generators: Store<Generator>,
+    pub memories: Store<Memory>,
+    pub immunes: Store<Immune>,
+    /// Field declarations checked by [`validate_world`]: the built-in CLD v6
+    /// schema plus any `@Schema` declarations
+    pub schema: Schema,
+    /// Every citizen in declaration order; indexed by [`CitizenId`]
+    declarations: Vec<CitizenRef>,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl World {
@@ -25,102 +66,532 @@ impl World {
     pub fn new() -> Self {
         World {
             origin: None,
-            timelines: HashMap::new(),
-            events: HashMap::new(),
-            core_events: HashMap::new(),
-            niches: HashMap::new(),
-            eras: HashMap::new(),
-            generators: HashMap::new(),
-            memories: HashMap::new(),
-            immunes: HashMap::new(),
+            timelines: Store::new(),
+            events: Store::new(),
+            core_events: Store::new(),
+            niches: Store::new(),
+            eras: Store::new(),
+            generators: Store::new(),
+            memories: Store::new(),
+            immunes: Store::new(),
+            schema: Schema::builtin(),
+            declarations: Vec::new(),
+        }
+    }
+
+    /// All citizens in the world, in declaration order
+    pub fn citizens(&self) -> Vec<&dyn CitizenData> {
+        self.declarations
+            .iter()
+            .filter_map(|citizen| self.citizen(&citizen.kind, &citizen.name))
+            .collect()
+    }
+
+    /// Every citizen in declaration order, by kind and name
+    pub fn declaration_order(&self) -> &[CitizenRef] {
+        &self.declarations
+    }
+
+    /// The stable id of a citizen
+    pub fn id(&self, kind: &str, name: &str) -> Option<CitizenId> {
+        self.declarations
+            .iter()
+            .position(|citizen| citizen.kind == kind && citizen.name == name)
+            .map(CitizenId)
+    }
+
+    /// Find a citizen by its stable id
+    pub fn by_id(&self, id: CitizenId) -> Option<&dyn CitizenData> {
+        let citizen = self.declarations.get(id.0)?;
+        self.citizen(&citizen.kind, &citizen.name)
+    }
+
+    /// Find a citizen by kind and name
+    pub fn citizen(&self, kind: &str, name: &str) -> Option<&dyn CitizenData> {
+        match kind {
+            "Origin" => self
+                .origin
+                .as_ref()
+                .filter(|origin| origin.name == name)
+                .map(|c| c as &dyn CitizenData),
+            "Timeline" => self.timelines.get(name).map(|c| c as &dyn CitizenData),
+            "Event" => self.events.get(name).map(|c| c as &dyn CitizenData),
+            "CoreEvent" => self.core_events.get(name).map(|c| c as &dyn CitizenData),
+            "Niche" => self.niches.get(name).map(|c| c as &dyn CitizenData),
+            "Era" => self.eras.get(name).map(|c| c as &dyn CitizenData),
+            "Generator" => self.generators.get(name).map(|c| c as &dyn CitizenData),
+            "Memory" => self.memories.get(name).map(|c| c as &dyn CitizenData),
+            "Immune" => self.immunes.get(name).map(|c| c as &dyn CitizenData),
+            _ => None,
+        }
+    }
+
+    /// The span of the name of the citizen `citizen` refers to
+    pub fn name_span(&self, citizen: &CitizenRef) -> Option<Span> {
+        self.citizen(&citizen.kind, &citizen.name).map(|c| c.name_span())
+    }
+
+    /// All citizens called `name`, of any kind
+    pub fn lookup(&self, name: &str) -> Vec<CitizenRef> {
+        CITIZEN_KINDS
+            .iter()
+            .filter_map(|kind| self.citizen(kind, name))
+            .map(CitizenRef::of)
+            .collect()
+    }
+
+    /// Citizens of one of `kinds` that `name` refers to when written inside
+    /// `namespace`
+    ///
+    /// The name is looked up in `namespace` first and then in each enclosing
+    /// namespace out to the root, stopping at the first one where it names a
+    /// citizen of an acceptable kind. Several results mean the name is
+    /// ambiguous in that namespace.
+    pub fn lookup_in(&self, namespace: &str, name: &str, kinds: &[String]) -> Vec<CitizenRef> {
+        let mut scope = namespace;
+        loop {
+            let found: Vec<CitizenRef> = self
+                .lookup(&qualify(scope, name))
+                .into_iter()
+                .filter(|citizen| kinds.contains(&citizen.kind))
+                .collect();
+            if !found.is_empty() || scope.is_empty() {
+                return found;
+            }
+            scope = namespace_of(scope);
+        }
+    }
+
+    /// The graph of references between citizens
+    ///
+    /// References that do not resolve are left out; [`validate_world`]
+    /// reports them.
+    pub fn reference_graph(&self) -> ReferenceGraph {
+        resolve(self).0
+    }
+
+    /// Build a world from a parsed document, applying its `@Schema`
+    /// declarations on top of the built-in schema
+    pub fn build_document(document: Document) -> (Self, Vec<CldError>) {
+        let (mut world, mut errors) = World::build(document.citizens);
+        for decl in &document.schemas {
+            if let Err(e) = world.schema.extend(decl) {
+                errors.push(e);
+            }
         }
+        (world, errors)
     }
 
     /// Build a world from a list of citizens
-    pub fn from_citizens(citizens: Vec<Citizen>) -> Result<Self, String> {
+    ///
+    /// Fails with every problem found if any citizen could not be placed.
+    pub fn from_citizens(citizens: Vec<Citizen>) -> Result<Self, Vec<CldError>> {
+        let (world, errors) = World::build(citizens);
+        if errors.is_empty() {
+            Ok(world)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Build a world from a list of citizens, collecting problems instead of
+    /// stopping at the first one
+    ///
+    /// Conflicting declarations are reported and skipped, so the returned
+    /// world can still be validated.
+    pub fn build(citizens: Vec<Citizen>) -> (Self, Vec<CldError>) {
         let mut world = World::new();
+        let mut errors = Vec::new();
         
         for citizen in citizens {
             match citizen {
-                Citizen::Origin(origin) => {
-                    if world.origin.is_some() {
-                        return Err("Multiple @Origin declarations found".to_string());
-                    }
-                    world.origin = Some(origin);
-                }
+                Citizen::Origin(origin) => world.place_origin(origin, &mut errors),
                 Citizen::Timeline(timeline) => {
-                    world.timelines.insert(timeline.name.clone(), timeline);
-                }
-                Citizen::Event(event) => {
-                    world.events.insert(event.name.clone(), event);
-                }
-                Citizen::CoreEvent(core_event) => {
-                    world.core_events.insert(core_event.name.clone(), core_event);
-                }
-                Citizen::Niche(niche) => {
-                    world.niches.insert(niche.name.clone(), niche);
-                }
-                Citizen::Era(era) => {
-                    world.eras.insert(era.name.clone(), era);
-                }
-                Citizen::Generator(generator) => {
-                    world.generators.insert(generator.name.clone(), generator);
-                }
-                Citizen::Memory(memory) => {
-                    world.memories.insert(memory.name.clone(), memory);
+                    let dropped = world.dropped_core_events(&timeline);
+                    if dropped.is_empty() {
+                        place(&mut world.timelines, &mut world.declarations, timeline, &mut errors);
+                    } else {
+                        errors.extend(dropped);
+                    }
                 }
-                Citizen::Immune(immune) => {
-                    world.immunes.insert(immune.name.clone(), immune);
+                Citizen::Event(event) => place(&mut world.events, &mut world.declarations, event, &mut errors),
+                Citizen::CoreEvent(core_event) if !core_event.mode.is_declare() => {
+                    errors.push(immutable_core_event(&core_event));
                 }
+                Citizen::CoreEvent(core_event) => place(&mut world.core_events, &mut world.declarations, core_event, &mut errors),
+                Citizen::Niche(niche) => place(&mut world.niches, &mut world.declarations, niche, &mut errors),
+                Citizen::Era(era) => place(&mut world.eras, &mut world.declarations, era, &mut errors),
+                Citizen::Generator(generator) => place(&mut world.generators, &mut world.declarations, generator, &mut errors),
+                Citizen::Memory(memory) => place(&mut world.memories, &mut world.declarations, memory, &mut errors),
+                Citizen::Immune(immune) => place(&mut world.immunes, &mut world.declarations, immune, &mut errors),
             }
         }
-        
-        Ok(world)
+
+        (world, errors)
     }
+
+    /// Add the Origin, or layer it on the existing one
+    ///
+    /// Only a `merge` or `override` of the same name may follow the first
+    /// @Origin; anything else breaks the `!Origin` rule. A patch that would
+    /// remove a core anchor breaks the `!CoreEvent` rule and is ignored.
+    fn place_origin(&mut self, mut origin: Origin, errors: &mut Vec<CldError>) {
+        let Some(previous) = self.origin.as_mut() else {
+            if !origin.mode.is_declare() {
+                errors.push(missing_patch_target(&origin));
+            }
+            self.declarations.push(CitizenRef::of(&origin));
+            self.origin = Some(origin);
+            return;
+        };
+
+        if origin.name == previous.name && !origin.mode.is_declare() {
+            let removed = removed_entries(&previous.fields, &origin, "core_anchors");
+            if !removed.is_empty() {
+                errors.extend(removed.into_iter().map(|(anchor, span)| {
+                    anchor_violation(
+                        format!(
+                            "`{}` of Origin '{}' would remove core anchor '{}'; core anchors are immutable",
+                            origin.mode.keyword().unwrap_or_default(),
+                            origin.name,
+                            anchor
+                        ),
+                        &origin,
+                        span,
+                        "anchored here",
+                    )
+                }));
+                return;
+            }
+        }
+
+        match origin.mode {
+            DeclMode::Merge if origin.name == previous.name => {
+                previous.fields.merge(std::mem::take(&mut origin.fields));
+            }
+            DeclMode::Override if origin.name == previous.name => {
+                *previous = origin;
+            }
+            _ => errors.push(CldError::RuleViolation {
+                rule: "!Origin".to_string(),
+                message: "Multiple @Origin declarations found".to_string(),
+                span: Some(origin.name_span),
+                severity: Severity::Error,
+                related: vec![Label {
+                    span: previous.name_span,
+                    message: "first @Origin declared here".to_string(),
+                    primary: false,
+                }],
+            }),
+        }
+    }
+
+    /// Errors for each CoreEvent in a timeline's history that a `merge` or
+    /// `override` of the timeline would drop
+    fn dropped_core_events(&self, timeline: &Timeline) -> Vec<CldError> {
+        let Some(previous) = self.timelines.get(&timeline.name).filter(|_| !timeline.mode.is_declare()) else {
+            return Vec::new();
+        };
+        removed_entries(&previous.fields, timeline, "events")
+            .into_iter()
+            .filter(|(event, _)| self.core_events.contains_key(event))
+            .map(|(event, span)| {
+                anchor_violation(
+                    format!(
+                        "`{}` of Timeline '{}' would drop CoreEvent '{}' from its history; CoreEvents are immutable",
+                        timeline.mode.keyword().unwrap_or_default(),
+                        timeline.name,
+                        event
+                    ),
+                    timeline,
+                    span,
+                    "in the history here",
+                )
+            })
+            .collect()
+    }
+}
+
+/// Identifiers in the list field `key` of `fields` that `patch` would
+/// remove, with their spans
+///
+/// A `merge` without the field keeps the list; an `override` without it
+/// removes every entry.
+fn removed_entries(fields: &Fields, patch: &dyn CitizenData, key: &str) -> Vec<(String, Span)> {
+    let kept: Vec<&String> = match patch.fields().get(key) {
+        Some(value) => identifiers(value).map(|(name, _)| name).collect(),
+        None if patch.mode() == DeclMode::Merge => return Vec::new(),
+        None => Vec::new(),
+    };
+    fields
+        .get(key)
+        .into_iter()
+        .flat_map(identifiers)
+        .filter(|(name, _)| !kept.contains(name))
+        .map(|(name, span)| (name.clone(), span))
+        .collect()
+}
+
+/// The identifiers of a list value, with their spans
+fn identifiers(value: &Value) -> impl Iterator<Item = (&String, Span)> {
+    value
+        .as_list()
+        .into_iter()
+        .flatten()
+        .filter_map(|item| Some((item.node.as_identifier()?, item.span)))
+}
+
+/// A patch that removes an anchor, pointing at the patch and at the entry
+/// it removes
+fn anchor_violation(message: String, patch: &dyn CitizenData, entry: Span, label: &str) -> CldError {
+    CldError::RuleViolation {
+        rule: "!CoreEvent".to_string(),
+        message,
+        span: Some(patch.name_span()),
+        severity: Severity::Error,
+        related: vec![Label {
+            span: entry,
+            message: label.to_string(),
+            primary: false,
+        }],
+    }
+}
+
+/// Add a citizen to its kind's table, honouring its declaration mode
+///
+/// A plain declaration of a name that is already taken is reported and
+/// skipped, so the first declaration wins. `merge` layers the new fields on
+/// the earlier declaration and `override` replaces it; either one without an
+/// earlier declaration is reported and then kept as a declaration.
+fn place<T: CitizenData>(
+    citizens: &mut Store<T>,
+    declarations: &mut Vec<CitizenRef>,
+    mut citizen: T,
+    errors: &mut Vec<CldError>,
+) {
+    let Some(previous) = citizens.get_mut(citizen.name()) else {
+        if !citizen.mode().is_declare() {
+            errors.push(missing_patch_target(&citizen));
+        }
+        declarations.push(CitizenRef::of(&citizen));
+        citizens.insert(citizen.name().to_string(), citizen);
+        return;
+    };
+
+    match citizen.mode() {
+        DeclMode::Declare => errors.push(CldError::DuplicateDeclaration {
+            kind: citizen.kind().to_string(),
+            name: citizen.name().to_string(),
+            span: citizen.name_span(),
+            previous: previous.name_span(),
+        }),
+        DeclMode::Merge => previous.fields_mut().merge(std::mem::take(citizen.fields_mut())),
+        DeclMode::Override => *previous = citizen,
+    }
+}
+
+/// A `merge` or `override` of a CoreEvent, which is ignored
+fn immutable_core_event(core_event: &CoreEvent) -> CldError {
+    CldError::rule(
+        "!CoreEvent",
+        format!(
+            "CoreEvent '{}' is immutable and cannot be changed with `{}`",
+            core_event.name,
+            core_event.mode.keyword().unwrap_or_default()
+        ),
+        Some(core_event.name_span),
+    )
+}
+
+/// A `merge` or `override` with no earlier declaration of the same name
+fn missing_patch_target(citizen: &dyn CitizenData) -> CldError {
+    CldError::rule(
+        "!Patch",
+        format!(
+            "@{}[{}] {} has no earlier declaration to apply to",
+            citizen.kind(),
+            citizen.name(),
+            citizen.mode().keyword().unwrap_or_default()
+        ),
+        Some(citizen.name_span()),
+    )
 }
 
 /// Validate a world against CLD v6 rules
-pub fn validate_world(world: &World) -> Result<(), String> {
+///
+/// Every rule is checked against every citizen; all errors and warnings are
+/// returned together, ordered by source position.
+pub fn validate_world(world: &World) -> Vec<CldError> {
+    let mut errors = Vec::new();
+
     // Validate !Origin rule - must exist
-    validate_origin_exists(world)?;
-    
-    // Validate !CoreEvent rule - core events in Origin.核心锚点 must be defined
-    validate_core_events(world)?;
+    validate_origin_exists(world, &mut errors);
+
+    // Warn about fields set twice, e.g. once in English and once in Chinese
+    validate_unique_fields(world, &mut errors);
+
+    // Check every field against the schema: required, known, typed, in range
+    errors.extend(world.schema.check(world));
+
+    // Lower to typed views, reporting what the schema cannot express
+    let (typed, lowering) = lower_world(world);
+    errors.extend(lowering);
+
+    // Every identifier in a reference field must name exactly one citizen,
+    // including the CoreEvents in Origin.core_anchors
+    let (references, unresolved) = resolve(world);
+    errors.extend(unresolved);
+
+    // Validate !CoreEvent rule - core events should be anchored in Origin.core_anchors
+    validate_core_events(world, &references, &mut errors);
+
+    // Events must not cause themselves, directly or indirectly
+    let causal = CausalGraph::from_references(world, &references);
+    errors.extend(validate_causality(&causal));
+
+    // Branches must fork from their parent's history and still reach every
+    // anchored CoreEvent
+    let branches = Branches::build(world, &references, &mut errors);
+    errors.extend(validate_convergence(world, &references, &branches));
+
+    // CoreEvents cannot be dropped by a branch, produced by a generator or
+    // preceded by their own effects
+    errors.extend(validate_anchors(world, &references, &causal, &branches));
+
+    // Eras must lie on a timeline's history without overlapping
+    let eras = Eras::build(world, &references, &branches, &mut errors);
+    errors.extend(validate_eras(world, &eras));
+
+    // `requires` and `effects` expressions must parse and type-check
+    errors.extend(validate_expressions(world));
+
+    // The Origin's entropy bounds and clamp policy must make sense
+    errors.extend(validate_entropy(world));
+
+    // Events must keep to the rules of the era they fall in
+    errors.extend(validate_era_rules(world, &typed, &references, &eras));
+
+    // Emotions must be well-formed tones, and events must name known ones
+    errors.extend(validate_emotions(world));
     
     // TODO: Add more validation rules
     
-    Ok(())
+    sort_by_position(&mut errors);
+    errors
+}
+
+/// Order diagnostics by where they occur, file by file, unlocated ones first
+pub fn sort_by_position(errors: &mut [CldError]) {
+    errors.sort_by_key(|e| e.span().map(|span| (span.file, span.start, span.end)));
 }
 
 /// Validate that an Origin exists
-fn validate_origin_exists(world: &World) -> Result<(), String> {
+fn validate_origin_exists(world: &World, errors: &mut Vec<CldError>) {
     if world.origin.is_none() {
-        return Err("!Origin missing: A world must have exactly one @Origin".to_string());
+        errors.push(CldError::rule("!Origin", "A world must have exactly one @Origin", None));
     }
-    Ok(())
 }
 
-/// Validate that all core events referenced in Origin.核心锚点 are defined
-fn validate_core_events(world: &World) -> Result<(), String> {
-    let origin = world.origin.as_ref().ok_or("!Origin missing")?;
-    
-    // Get the 核心锚点 field from Origin
-    if let Some(core_anchors_value) = origin.fields.get("核心锚点") {
-        if let Some(core_anchors) = core_anchors_value.as_list() {
-            // Check that each anchor is defined as a CoreEvent
-            for anchor in core_anchors {
-                if let Some(identifier) = anchor.as_identifier() {
-                    if !world.core_events.contains_key(identifier) {
-                        return Err(format!("CoreEvent '{}' referenced in Origin.核心锚点 is not defined", identifier));
-                    }
-                } else {
-                    return Err("Origin.核心锚点 must contain only identifiers".to_string());
-                }
+/// Warn about core events that are not anchored in Origin.core_anchors
+///
+/// Whether each anchor names a defined CoreEvent is checked by reference
+/// resolution.
+fn validate_core_events(world: &World, graph: &ReferenceGraph, errors: &mut Vec<CldError>) {
+    // A missing Origin is reported by validate_origin_exists
+    let Some(origin) = world.origin.as_ref() else {
+        return;
+    };
+    let origin = CitizenRef::of(origin);
+    let anchored: Vec<&CitizenRef> = graph.targets(&origin, "core_anchors").collect();
+
+    // Every CoreEvent is expected to be one of the Origin's anchors
+    for core_event in world.core_events.values() {
+        if !anchored.contains(&&CitizenRef::of(core_event)) {
+            errors.push(CldError::rule_warning(
+                "!CoreEvent",
+                format!("CoreEvent '{}' is not listed in Origin.core_anchors", core_event.name),
+                Some(core_event.name_span),
+            ));
+        }
+    }
+}
+
+/// Warn when a citizen sets the same canonical field more than once
+fn validate_unique_fields(world: &World, errors: &mut Vec<CldError>) {
+    for citizen in world.citizens() {
+        let mut seen: HashMap<&str, &Field> = HashMap::new();
+        for field in citizen.fields() {
+            if let Some(previous) = seen.insert(&field.key, field) {
+                errors.push(CldError::DuplicateField {
+                    field: format!("{}[{}].{}", citizen.kind(), citizen.name(), field.key),
+                    written_as: vec![
+                        previous.written_key().to_string(),
+                        field.written_key().to_string(),
+                    ],
+                    span: field.key_span,
+                    previous: previous.key_span,
+                });
             }
-        } else {
-            return Err("Origin.核心锚点 must be a list".to_string());
         }
     }
-    
-    Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::CLDParser;
+
+    const BASE: &str = r#"
+        @Origin[o] { core_anchors: [big_bang] }
+        @CoreEvent[big_bang] { }
+        @Event[stars] { caused_by: [big_bang] }
+        @Timeline[main] { events: [big_bang, stars] }
+    "#;
+
+    fn build(patch: &str) -> (World, Vec<CldError>) {
+        let document = CLDParser::parse_document(&format!("{}{}", BASE, patch)).expect("test source parses");
+        World::build_document(document)
+    }
+
+    fn rules(errors: &[CldError]) -> Vec<&str> {
+        errors
+            .iter()
+            .map(|error| match error {
+                CldError::RuleViolation { rule, .. } => rule.as_str(),
+                _ => "",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn patches_cannot_remove_core_anchors() {
+        let (world, errors) = build("@Origin[o] merge { core_anchors: [] }");
+        assert_eq!(rules(&errors), ["!CoreEvent"]);
+        let origin = world.origin.expect("origin is kept");
+        assert!(origin.fields.get("core_anchors").and_then(Value::as_list).is_some_and(|list| list.len() == 1));
+
+        let (_, errors) = build("@Origin[o] override { }");
+        assert_eq!(rules(&errors), ["!CoreEvent"]);
+    }
+
+    #[test]
+    fn patches_cannot_drop_core_events_from_a_history() {
+        let (world, errors) = build("@Timeline[main] override { events: [stars] }");
+        assert_eq!(rules(&errors), ["!CoreEvent"]);
+        let events = world.timelines.get("main").and_then(|timeline| timeline.fields.get("events"));
+        assert!(events.and_then(Value::as_list).is_some_and(|list| list.len() == 2));
+    }
+
+    #[test]
+    fn patches_may_keep_and_add_anchors() {
+        let (_, errors) = build(
+            r#"
+            @CoreEvent[dawn] { }
+            @Origin[o] merge { core_anchors: [big_bang, dawn] }
+            @Timeline[main] override { events: [big_bang, dawn] }
+            @Timeline[main] merge { description: "kept" }
+            "#,
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+    }
+}
@@ -0,0 +1,134 @@
+//! Declaration-ordered citizen tables
+
+use serde::de::{MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+
+/// A name-keyed table that iterates in insertion order
+///
+/// Replacing an entry keeps its original position, so an `override` of a
+/// citizen does not move it. Serialized as a JSON object whose keys appear
+/// in that order.
+#[derive(Debug, Clone)]
+pub struct Store<T> {
+    entries: Vec<(String, T)>,
+    index: HashMap<String, usize>,
+}
+
+impl<T> Default for Store<T> {
+    fn default() -> Self {
+        Store {
+            entries: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+}
+
+impl<T> Store<T> {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Insert `value` under `name`, returning the value it replaced
+    pub fn insert(&mut self, name: impl Into<String>, value: T) -> Option<T> {
+        let name = name.into();
+        match self.index.get(&name) {
+            Some(&position) => Some(std::mem::replace(&mut self.entries[position].1, value)),
+            None => {
+                self.index.insert(name.clone(), self.entries.len());
+                self.entries.push((name, value));
+                None
+            }
+        }
+    }
+
+    /// Get the value stored under `name`
+    pub fn get(&self, name: &str) -> Option<&T> {
+        self.index.get(name).map(|&position| &self.entries[position].1)
+    }
+
+    /// Get the value stored under `name` for modification
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut T> {
+        let position = *self.index.get(name)?;
+        Some(&mut self.entries[position].1)
+    }
+
+    /// Check whether a value is stored under `name`
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.index.contains_key(name)
+    }
+
+    /// Position of `name` in insertion order
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.index.get(name).copied()
+    }
+
+    /// Iterate over names and values in insertion order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &T)> {
+        self.entries.iter().map(|(name, value)| (name.as_str(), value))
+    }
+
+    /// Iterate over names in insertion order
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Iterate over values in insertion order
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.entries.iter().map(|(_, value)| value)
+    }
+
+    /// Number of entries
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check whether the store is empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<T: PartialEq> PartialEq for Store<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.entries == other.entries
+    }
+}
+
+impl<T: Serialize> Serialize for Store<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.entries.len()))?;
+        for (name, value) in &self.entries {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Store<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct StoreVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>> Visitor<'de> for StoreVisitor<T> {
+            type Value = Store<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a map of names to citizens")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+                let mut store = Store::new();
+                while let Some((name, value)) = access.next_entry::<String, T>()? {
+                    store.insert(name, value);
+                }
+                Ok(store)
+            }
+        }
+
+        deserializer.deserialize_map(StoreVisitor(PhantomData))
+    }
+}